@@ -0,0 +1,164 @@
+// ABOUTME: Accent color separation for black/white/red and black/white/yellow panels
+// ABOUTME: Classifies pixels by tunable HSV thresholds and renders the accent plane
+
+use image::{DynamicImage, ImageBuffer, Luma, Rgb, RgbImage};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccentColor {
+    Red,
+    Yellow,
+}
+
+impl AccentColor {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "red" => Some(AccentColor::Red),
+            "yellow" => Some(AccentColor::Yellow),
+            _ => None,
+        }
+    }
+
+    /// Ink color used when rendering image outputs.
+    pub fn rgb(self) -> Rgb<u8> {
+        match self {
+            AccentColor::Red => Rgb([255, 0, 0]),
+            AccentColor::Yellow => Rgb([255, 255, 0]),
+        }
+    }
+
+    /// Default hue range in degrees, minimum saturation, and minimum value.
+    pub fn default_thresholds(self) -> ((f32, f32), f32, f32) {
+        match self {
+            AccentColor::Red => ((330.0, 25.0), 0.45, 0.25),
+            AccentColor::Yellow => ((40.0, 70.0), 0.45, 0.45),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AccentSettings {
+    pub color: AccentColor,
+    /// Hue range in degrees; wraps through 0 when the start exceeds the end.
+    pub hue_range: (f32, f32),
+    pub min_saturation: f32,
+    pub min_value: f32,
+    /// Optional path for an image highlighting the accent-plane pixels.
+    pub preview: Option<String>,
+}
+
+/// Parses a hue range such as `330-25` (wrapping through red) or `40-70`.
+pub fn parse_hue_range(spec: &str) -> Result<(f32, f32), Box<dyn std::error::Error>> {
+    let (start, end) = spec
+        .split_once('-')
+        .ok_or_else(|| format!("hue range '{}' must be START-END in degrees", spec))?;
+    let start: f32 = start.trim().parse()?;
+    let end: f32 = end.trim().parse()?;
+    if !(0.0..=360.0).contains(&start) || !(0.0..=360.0).contains(&end) {
+        return Err(format!("hue range '{}' must be within 0-360", spec).into());
+    }
+    Ok((start, end))
+}
+
+/// Parses a minimum saturation or value, which must lie within 0.0-1.0.
+pub fn parse_min_threshold(spec: &str) -> Result<f32, Box<dyn std::error::Error>> {
+    let value: f32 = spec.trim().parse()?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!("accent minimum '{}' must be within 0.0-1.0", spec).into());
+    }
+    Ok(value)
+}
+
+/// Converts RGB to hue in degrees, saturation, and value (both 0.0-1.0).
+fn rgb_to_hsv(pixel: Rgb<u8>) -> (f32, f32, f32) {
+    let [r, g, b] = pixel.0.map(|channel| channel as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+fn is_accent(pixel: Rgb<u8>, settings: &AccentSettings) -> bool {
+    let (hue, saturation, value) = rgb_to_hsv(pixel);
+    let (start, end) = settings.hue_range;
+    let in_range = if start <= end {
+        hue >= start && hue <= end
+    } else {
+        hue >= start || hue <= end
+    };
+
+    in_range && saturation >= settings.min_saturation && value >= settings.min_value
+}
+
+/// Builds the accent mask: 255 where a pixel belongs to the accent plane.
+pub fn classify(img: &DynamicImage, settings: &AccentSettings) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let rgb = img.to_rgb8();
+    ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+        Luma([if is_accent(*rgb.get_pixel(x, y), settings) {
+            255
+        } else {
+            0
+        }])
+    })
+}
+
+/// Clears accent pixels to white in the tone image so dithering does not
+/// spend black ink on areas the accent plane will cover.
+pub fn clear_accent(
+    mut img: ImageBuffer<Luma<u8>, Vec<u8>>,
+    mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    for (pixel, accent) in img.pixels_mut().zip(mask.pixels()) {
+        if accent[0] >= 128 {
+            pixel[0] = 255;
+        }
+    }
+    img
+}
+
+/// Renders black, white, and accent ink as a viewable RGB image.
+pub fn compose(
+    mono: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    color: AccentColor,
+) -> RgbImage {
+    ImageBuffer::from_fn(mono.width(), mono.height(), |x, y| {
+        if mask.get_pixel(x, y)[0] >= 128 {
+            color.rgb()
+        } else {
+            let value = mono.get_pixel(x, y)[0];
+            Rgb([value, value, value])
+        }
+    })
+}
+
+/// Writes a tuning preview: the tone image dimmed to gray with every
+/// accent-plane pixel painted in full magenta, which stands out against
+/// both the image and either accent ink.
+pub fn save_preview(
+    tone: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    mask: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let preview: RgbImage = ImageBuffer::from_fn(tone.width(), tone.height(), |x, y| {
+        if mask.get_pixel(x, y)[0] >= 128 {
+            Rgb([255, 0, 255])
+        } else {
+            let value = 64 + tone.get_pixel(x, y)[0] / 2;
+            Rgb([value, value, value])
+        }
+    });
+    preview.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+// ABOUTME: Ghost-clearing frame patterns for full-flash panel refreshes
+// ABOUTME: Renders solid and checkerboard frames at a display's native size
+
+use image::{ImageBuffer, Luma};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Black,
+    White,
+    Checker,
+    InverseChecker,
+}
+
+/// The standard clearing sequence: drive every pixel fully black, then fully
+/// white, then toggle each pixel both ways with opposite checkerboards.
+pub const SEQUENCE: [Pattern; 4] = [
+    Pattern::Black,
+    Pattern::White,
+    Pattern::Checker,
+    Pattern::InverseChecker,
+];
+
+impl Pattern {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "black" => Some(Pattern::Black),
+            "white" => Some(Pattern::White),
+            "checker" => Some(Pattern::Checker),
+            "inverse-checker" => Some(Pattern::InverseChecker),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Black => "black",
+            Pattern::White => "white",
+            Pattern::Checker => "checker",
+            Pattern::InverseChecker => "inverse-checker",
+        }
+    }
+}
+
+/// Renders a pattern as a black/white frame. Checker squares are
+/// `checker_size` pixels wide, with the top-left square black.
+pub fn render(
+    pattern: Pattern,
+    width: u32,
+    height: u32,
+    checker_size: u32,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let checker_size = checker_size.max(1);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let black_square = (x / checker_size + y / checker_size) % 2 == 0;
+        let white = match pattern {
+            Pattern::Black => false,
+            Pattern::White => true,
+            Pattern::Checker => !black_square,
+            Pattern::InverseChecker => black_square,
+        };
+        Luma([if white { 255 } else { 0 }])
+    })
+}
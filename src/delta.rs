@@ -0,0 +1,271 @@
+// ABOUTME: Delta stream encoding between successive packed framebuffers
+// ABOUTME: Emits offset+length+data records covering only the changed byte runs
+
+use crate::raw::{self, Compression};
+use image::{ImageBuffer, Luma};
+
+/// Magic bytes at the start of every delta stream.
+pub const DELTA_MAGIC: &[u8; 4] = b"EIND";
+/// Delta stream layout version written after the magic.
+pub const DELTA_VERSION: u8 = 1;
+/// Size in bytes of each record header (offset u32 + length u16).
+pub const RECORD_HEADER_LEN: usize = 6;
+
+const MAX_RECORD_LEN: usize = u16::MAX as usize;
+
+/// A run of changed bytes in the new framebuffer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Record {
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// Returns true when the output path should be written as a delta stream.
+pub fn is_delta_path(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".delta")
+}
+
+/// Finds the changed byte runs between two equally sized buffers. Runs
+/// separated by fewer unchanged bytes than a record header are merged, since
+/// resending those bytes is cheaper than starting a new record.
+pub fn diff_records(previous: &[u8], current: &[u8]) -> Vec<Record> {
+    let mut records: Vec<Record> = Vec::new();
+    let mut i = 0;
+
+    while i < current.len() {
+        if previous[i] == current[i] {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < current.len() && previous[i] != current[i] {
+            i += 1;
+        }
+
+        match records.last_mut() {
+            Some(last)
+                if start - (last.offset + last.len) < RECORD_HEADER_LEN
+                    && i - last.offset <= MAX_RECORD_LEN =>
+            {
+                last.len = i - last.offset;
+            }
+            _ => {
+                let mut offset = start;
+                while i - offset > MAX_RECORD_LEN {
+                    records.push(Record {
+                        offset,
+                        len: MAX_RECORD_LEN,
+                    });
+                    offset += MAX_RECORD_LEN;
+                }
+                records.push(Record {
+                    offset,
+                    len: i - offset,
+                });
+            }
+        }
+    }
+
+    records
+}
+
+/// Serializes a delta stream: magic, version, compression id, width, height,
+/// framebuffer length, record count, then the records (`offset u32,
+/// length u16, data` each), compressed as one block after the header. All
+/// multi-byte fields are little-endian.
+pub fn encode_delta(
+    previous: &[u8],
+    current: &[u8],
+    width: u32,
+    height: u32,
+    compression: Compression,
+) -> Result<(Vec<u8>, usize), Box<dyn std::error::Error>> {
+    if previous.len() != current.len() {
+        return Err(format!(
+            "previous frame is {} bytes but the new frame is {} bytes",
+            previous.len(),
+            current.len()
+        )
+        .into());
+    }
+
+    let width = u16::try_from(width).map_err(|_| "image too wide for delta stream")?;
+    let height = u16::try_from(height).map_err(|_| "image too tall for delta stream")?;
+    let records = diff_records(previous, current);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(DELTA_MAGIC);
+    out.push(DELTA_VERSION);
+    out.push(compression.id());
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&(current.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(records.len() as u32).to_le_bytes());
+
+    let mut body = Vec::new();
+    for record in &records {
+        body.extend_from_slice(&(record.offset as u32).to_le_bytes());
+        body.extend_from_slice(&(record.len as u16).to_le_bytes());
+        body.extend_from_slice(&current[record.offset..record.offset + record.len]);
+    }
+    out.extend(raw::compress(&body, compression)?);
+
+    Ok((out, records.len()))
+}
+
+/// Writes a delta stream that turns the raw buffer at `previous_path` into
+/// the packed form of `img`. Returns the number of records written.
+pub fn save_delta(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    accent: Option<&ImageBuffer<Luma<u8>, Vec<u8>>>,
+    previous_path: &str,
+    path: &str,
+    compression: Compression,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let previous = raw::load_raw(previous_path)?;
+    let current = raw::pack_planes(img, accent);
+    let (width, height) = img.dimensions();
+    let (bytes, record_count) = encode_delta(&previous, &current, width, height, compression)?;
+    std::fs::write(path, bytes)?;
+    Ok(record_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_LEN: usize = 18;
+
+    /// Applies a delta stream to `previous` the way device firmware would.
+    fn apply(previous: &[u8], stream: &[u8]) -> Vec<u8> {
+        assert_eq!(&stream[0..4], DELTA_MAGIC);
+        assert_eq!(stream[4], DELTA_VERSION);
+        let compression = Compression::from_id(stream[5]).unwrap();
+        let records = raw::decompress(&stream[HEADER_LEN..], compression).unwrap();
+        let frame_len = u32::from_le_bytes(stream[10..14].try_into().unwrap()) as usize;
+        let record_count = u32::from_le_bytes(stream[14..18].try_into().unwrap()) as usize;
+        assert_eq!(frame_len, previous.len());
+
+        let mut frame = previous.to_vec();
+        let mut pos = 0;
+        for _ in 0..record_count {
+            let offset = u32::from_le_bytes(records[pos..pos + 4].try_into().unwrap()) as usize;
+            let len = u16::from_le_bytes(records[pos + 4..pos + 6].try_into().unwrap()) as usize;
+            pos += RECORD_HEADER_LEN;
+            frame[offset..offset + len].copy_from_slice(&records[pos..pos + len]);
+            pos += len;
+        }
+        assert_eq!(pos, records.len());
+        frame
+    }
+
+    fn round_trip(previous: &[u8], current: &[u8]) -> usize {
+        let (stream, record_count) =
+            encode_delta(previous, current, 8, 8, Compression::None).unwrap();
+        assert_eq!(apply(previous, &stream), current);
+        for compression in [Compression::Rle, Compression::Deflate] {
+            let (packed, _) = encode_delta(previous, current, 8, 8, compression).unwrap();
+            assert_eq!(packed[5], compression.id());
+            assert_eq!(apply(previous, &packed), current);
+        }
+        record_count
+    }
+
+    #[test]
+    fn identical_frames_have_no_records() {
+        let frame = vec![0x5A; 1000];
+        assert!(diff_records(&frame, &frame).is_empty());
+        let (stream, record_count) = encode_delta(&frame, &frame, 8, 8, Compression::None).unwrap();
+        assert_eq!(record_count, 0);
+        assert_eq!(stream.len(), HEADER_LEN);
+    }
+
+    #[test]
+    fn runs_merge_across_short_gaps() {
+        let previous = vec![0u8; 100];
+        let mut current = previous.clone();
+        current[10..15].fill(1);
+        // Gap of RECORD_HEADER_LEN - 1 unchanged bytes: cheaper to resend.
+        let second = 15 + RECORD_HEADER_LEN - 1;
+        current[second..second + 3].fill(1);
+
+        assert_eq!(
+            diff_records(&previous, &current),
+            vec![Record {
+                offset: 10,
+                len: second + 3 - 10
+            }]
+        );
+        assert_eq!(round_trip(&previous, &current), 1);
+    }
+
+    #[test]
+    fn runs_stay_separate_across_header_sized_gaps() {
+        let previous = vec![0u8; 100];
+        let mut current = previous.clone();
+        current[10..15].fill(1);
+        let second = 15 + RECORD_HEADER_LEN;
+        current[second..second + 3].fill(1);
+
+        assert_eq!(diff_records(&previous, &current).len(), 2);
+        assert_eq!(round_trip(&previous, &current), 2);
+    }
+
+    #[test]
+    fn long_runs_split_at_u16_max() {
+        let previous = vec![0u8; 200_000];
+        let mut current = previous.clone();
+        current[5..5 + 150_000].fill(0xFF);
+
+        let records = diff_records(&previous, &current);
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|record| record.len <= u16::MAX as usize));
+        assert_eq!(
+            records.iter().map(|record| record.len).sum::<usize>(),
+            150_000
+        );
+        assert_eq!(round_trip(&previous, &current), 3);
+    }
+
+    #[test]
+    fn merged_runs_do_not_exceed_u16_max() {
+        let previous = vec![0u8; 100_000];
+        let mut current = previous.clone();
+        current[0..65_000].fill(1);
+        current[65_002..66_000].fill(1);
+
+        let records = diff_records(&previous, &current);
+        assert!(records.iter().all(|record| record.len <= u16::MAX as usize));
+        round_trip(&previous, &current);
+    }
+
+    #[test]
+    fn scattered_changes_round_trip() {
+        let previous: Vec<u8> = (0..5000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut current = previous.clone();
+        for i in (0..5000).step_by(37) {
+            current[i] ^= 0xFF;
+        }
+        current[4990..].fill(0);
+        round_trip(&previous, &current);
+    }
+
+    #[test]
+    fn compression_shrinks_repetitive_records() {
+        let previous = vec![0u8; 20_000];
+        let mut current = previous.clone();
+        current[100..10_100].fill(0xFF);
+
+        let (bare, _) = encode_delta(&previous, &current, 8, 8, Compression::None).unwrap();
+        let (rle, _) = encode_delta(&previous, &current, 8, 8, Compression::Rle).unwrap();
+        assert_eq!(bare[5], 0);
+        assert!(rle.len() < bare.len() / 10);
+        assert_eq!(apply(&previous, &rle), current);
+    }
+
+    #[test]
+    fn length_mismatch_is_an_error() {
+        assert!(encode_delta(&[0u8; 10], &[0u8; 11], 8, 8, Compression::None).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+// ABOUTME: Display profiles describing panel resolution and tone response
+// ABOUTME: Provides built-in panels and loads community/calibrated profile files
+
+use std::path::Path;
+
+/// A target panel: its native resolution and, optionally, a measured tone
+/// response that replaces the generic gamma curve.
+#[derive(Clone, Debug)]
+pub struct DisplayProfile {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub gamma: Option<f32>,
+    pub tone_curve: Option<Vec<(u8, u8)>>,
+}
+
+const BUILTIN_PROFILES: &[(&str, u32, u32)] = &[
+    ("trmnl", 800, 480),
+    ("waveshare-7in5-v2", 800, 480),
+    ("waveshare-4in2", 400, 300),
+    ("waveshare-2in9", 296, 128),
+    ("waveshare-2in13-v4", 250, 122),
+    ("gooddisplay-gdey075t7", 800, 480),
+    ("gooddisplay-gdey042t81", 400, 300),
+    ("inky-impression-73", 800, 480),
+    ("inky-phat", 212, 104),
+];
+
+/// Names of the profiles compiled into the binary.
+pub fn builtin_names() -> Vec<&'static str> {
+    BUILTIN_PROFILES.iter().map(|(name, _, _)| *name).collect()
+}
+
+/// Resolves `--display`: an existing file is loaded as a profile, anything
+/// else is looked up among the built-in panels.
+pub fn resolve(name_or_path: &str) -> Result<DisplayProfile, Box<dyn std::error::Error>> {
+    if Path::new(name_or_path).is_file() {
+        return load_profile(name_or_path);
+    }
+
+    BUILTIN_PROFILES
+        .iter()
+        .find(|(name, _, _)| *name == name_or_path)
+        .map(|(name, width, height)| DisplayProfile {
+            name: name.to_string(),
+            width: *width,
+            height: *height,
+            gamma: None,
+            tone_curve: None,
+        })
+        .ok_or_else(|| {
+            format!(
+                "unknown display '{}' (built-in: {})",
+                name_or_path,
+                builtin_names().join(", ")
+            )
+            .into()
+        })
+}
+
+/// Loads a profile file made of `key = value` lines. Recognized keys are
+/// `name`, `width`, `height`, `gamma`, and `tone`, where `tone` lists
+/// `input:output` control points of the measured response. `#` starts a
+/// comment.
+pub fn load_profile(path: &str) -> Result<DisplayProfile, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut profile = DisplayProfile {
+        name: Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        width: 0,
+        height: 0,
+        gamma: None,
+        tone_curve: None,
+    };
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}:{}: expected 'key = value'", path, line_number + 1))?;
+        let value = value.trim();
+
+        match key.trim() {
+            "name" => profile.name = value.to_string(),
+            "width" => profile.width = value.parse()?,
+            "height" => profile.height = value.parse()?,
+            "gamma" => profile.gamma = Some(value.parse()?),
+            "tone" => profile.tone_curve = Some(parse_tone_curve(value)?),
+            other => {
+                return Err(format!("{}:{}: unknown key '{}'", path, line_number + 1, other).into())
+            }
+        }
+    }
+
+    if profile.width == 0 || profile.height == 0 {
+        return Err(format!("{}: profile must set width and height", path).into());
+    }
+
+    Ok(profile)
+}
+
+/// Parses whitespace-separated `input:output` control points of a measured
+/// response, sorted by input. Outputs must rise with the inputs, since the
+/// response is inverted to correct for it.
+pub fn parse_tone_curve(spec: &str) -> Result<Vec<(u8, u8)>, Box<dyn std::error::Error>> {
+    let mut points = Vec::new();
+    for point in spec.split_whitespace() {
+        let (input, output) = point
+            .split_once(':')
+            .ok_or_else(|| format!("tone point '{}' must be input:output", point))?;
+        points.push((input.parse()?, output.parse()?));
+    }
+
+    if points.len() < 2 {
+        return Err("tone curve needs at least two points".into());
+    }
+    points.sort_by_key(|(input, _)| *input);
+    for pair in points.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            return Err(format!("tone curve lists input {} twice", pair[0].0).into());
+        }
+        if pair[0].1 >= pair[1].1 {
+            return Err(format!(
+                "tone response must increase: {}:{} is followed by {}:{}",
+                pair[0].0, pair[0].1, pair[1].0, pair[1].1
+            )
+            .into());
+        }
+    }
+
+    Ok(points)
+}
+
+/// Builds the correcting lookup table for a measured response by inverting
+/// it: each wanted level maps to the input the panel renders at that level.
+/// A panel that renders dark (outputs below inputs) is driven lighter.
+pub fn correction_lut(measured: &[(u8, u8)]) -> Vec<u8> {
+    let inverted: Vec<(u8, u8)> = measured
+        .iter()
+        .map(|&(input, output)| (output, input))
+        .collect();
+    tone_curve_lut(&inverted)
+}
+
+/// Expands control points into a 256-entry lookup table by linear
+/// interpolation, holding the end values flat outside the measured range.
+fn tone_curve_lut(points: &[(u8, u8)]) -> Vec<u8> {
+    (0..256u32)
+        .map(|i| {
+            let upper = points
+                .iter()
+                .position(|(input, _)| *input as u32 >= i)
+                .unwrap_or(points.len() - 1);
+            if upper == 0 || points[upper].0 as u32 <= i {
+                return points[upper].1;
+            }
+
+            let (x0, y0) = points[upper - 1];
+            let (x1, y1) = points[upper];
+            let t = (i - x0 as u32) as f32 / (x1 - x0) as f32;
+            (y0 as f32 + (y1 as f32 - y0 as f32) * t).round() as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_profile(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "eink-image-test-{}-{}.profile",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn parse_tone_curve_sorts_points() {
+        let points = parse_tone_curve("255:255  0:0 128:90").unwrap();
+        assert_eq!(points, vec![(0, 0), (128, 90), (255, 255)]);
+    }
+
+    #[test]
+    fn parse_tone_curve_rejects_bad_input() {
+        assert!(parse_tone_curve("0:0").is_err());
+        assert!(parse_tone_curve("0:0 255").is_err());
+        assert!(parse_tone_curve("0:0 300:255").is_err());
+        assert!(parse_tone_curve("0:0 128:90 128:100 255:255").is_err());
+        assert!(parse_tone_curve("0:0 128:200 192:150 255:255").is_err());
+    }
+
+    #[test]
+    fn tone_curve_lut_hits_control_points_and_interpolates() {
+        let lut = tone_curve_lut(&[(0, 0), (100, 50), (200, 250)]);
+        assert_eq!(lut.len(), 256);
+        assert_eq!(lut[0], 0);
+        assert_eq!(lut[100], 50);
+        assert_eq!(lut[200], 250);
+        assert_eq!(lut[50], 25);
+        assert_eq!(lut[150], 150);
+    }
+
+    #[test]
+    fn tone_curve_lut_holds_ends_flat() {
+        let lut = tone_curve_lut(&[(20, 10), (200, 240)]);
+        assert!(lut[..=20].iter().all(|&value| value == 10));
+        assert!(lut[200..].iter().all(|&value| value == 240));
+    }
+
+    #[test]
+    fn correction_lut_inverts_a_dark_panel() {
+        let measured = [(0, 0), (128, 64), (255, 255)];
+        let lut = correction_lut(&measured);
+        // To appear at level 64, the panel needs input 128.
+        assert_eq!(lut[64], 128);
+        assert_eq!(lut[0], 0);
+        assert_eq!(lut[255], 255);
+        assert!((1..255).all(|i| lut[i] >= i as u8));
+    }
+
+    #[test]
+    fn load_profile_reads_keys_and_comments() {
+        let path = write_profile(
+            "valid",
+            "# panel\nname = test-panel\nwidth = 400 # native\nheight = 300\ntone = 0:0 255:255\n",
+        );
+        let profile = load_profile(&path).unwrap();
+        assert_eq!(profile.name, "test-panel");
+        assert_eq!((profile.width, profile.height), (400, 300));
+        assert_eq!(profile.tone_curve, Some(vec![(0, 0), (255, 255)]));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn load_profile_rejects_unknown_key() {
+        let path = write_profile("unknown", "width = 400\nheight = 300\ncolour = red\n");
+        let error = load_profile(&path).unwrap_err().to_string();
+        assert!(error.contains("unknown key 'colour'"), "{}", error);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn load_profile_requires_width_and_height() {
+        let path = write_profile("size", "name = no-size\nwidth = 400\n");
+        let error = load_profile(&path).unwrap_err().to_string();
+        assert!(error.contains("width and height"), "{}", error);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+// ABOUTME: ESPHome configuration snippet generation for exported images
+// ABOUTME: Writes the image: YAML block matching the converted asset's properties
+
+use std::path::{Path, PathBuf};
+
+/// Path of the snippet written next to an exported image. The image's full
+/// file name is kept, so `frame.png` and `frame.bmp` get separate snippets.
+pub fn snippet_path(output_path: &str) -> PathBuf {
+    let mut path = Path::new(output_path).as_os_str().to_owned();
+    path.push(".yaml");
+    PathBuf::from(path)
+}
+
+/// Builds an ESPHome-safe id from the output file name, extension included,
+/// so images sharing a stem still get distinct ids.
+fn image_id(output_path: &str) -> String {
+    let name = Path::new(output_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !id.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        id.insert_str(0, "img_");
+    }
+    id
+}
+
+/// Renders the `image:` section for an asset of the given size and ESPHome
+/// image type (`BINARY`, or `RGB24` for tri-color). The image is already
+/// dithered, so ESPHome's own dithering is disabled.
+pub fn render_snippet(output_path: &str, width: u32, height: u32, image_type: &str) -> String {
+    let file_name = Path::new(output_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| output_path.to_string());
+
+    format!(
+        "# Generated by eink-image for {file}\n\
+         image:\n  \
+           - file: \"{file}\"\n    \
+             id: {id}\n    \
+             type: {image_type}\n    \
+             resize: {width}x{height}\n    \
+             dither: NONE\n",
+        file = file_name,
+        id = image_id(output_path),
+        image_type = image_type,
+        width = width,
+        height = height,
+    )
+}
+
+/// Writes the snippet next to the exported image and returns its path.
+pub fn save_snippet(
+    output_path: &str,
+    width: u32,
+    height: u32,
+    image_type: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = snippet_path(output_path);
+    std::fs::write(
+        &path,
+        render_snippet(output_path, width, height, image_type),
+    )?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_sharing_a_stem_get_separate_snippets_and_ids() {
+        assert_eq!(
+            snippet_path("out/frame.png"),
+            PathBuf::from("out/frame.png.yaml")
+        );
+        assert_ne!(snippet_path("frame.png"), snippet_path("frame.bmp"));
+        assert_eq!(image_id("frame.png"), "frame_png");
+        assert_eq!(image_id("2024-frame.bmp"), "img_2024_frame_bmp");
+    }
+}
@@ -0,0 +1,176 @@
+// ABOUTME: Clustered-dot halftone screening with selectable dot shapes
+// ABOUTME: Each shape has a growth function so screened tone matches input tone
+
+use image::{ImageBuffer, Luma};
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DotShape {
+    Round,
+    Square,
+    Line,
+    Diamond,
+}
+
+impl DotShape {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "round" => Some(DotShape::Round),
+            "square" => Some(DotShape::Square),
+            "line" => Some(DotShape::Line),
+            "diamond" => Some(DotShape::Diamond),
+            _ => None,
+        }
+    }
+
+    /// Growth function: the fraction of the cell already inked when the dot
+    /// first reaches cell position (u, v), with both in -1.0..=1.0. A pixel is
+    /// inked when the wanted ink coverage exceeds this value, so a flat gray
+    /// of darkness d covers d of every cell on average.
+    fn coverage_at(self, u: f32, v: f32) -> f32 {
+        match self {
+            DotShape::Round => {
+                let r = (u * u + v * v).sqrt();
+                if r <= 1.0 {
+                    PI * r * r / 4.0
+                } else {
+                    // Circle clipped by the cell: subtract the four segments
+                    // that spill past the cell edges.
+                    let segment = r * r * (1.0 / r).acos() - (r * r - 1.0).sqrt();
+                    ((PI * r * r - 4.0 * segment) / 4.0).min(1.0)
+                }
+            }
+            DotShape::Square => {
+                let s = u.abs().max(v.abs());
+                s * s
+            }
+            DotShape::Line => v.abs(),
+            DotShape::Diamond => {
+                let r = u.abs() + v.abs();
+                if r <= 1.0 {
+                    r * r / 2.0
+                } else {
+                    1.0 - (2.0 - r).powi(2) / 2.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HalftoneSettings {
+    pub shape: DotShape,
+    /// Screen period in pixels.
+    pub cell_size: f32,
+    /// Screen angle in degrees.
+    pub angle: f32,
+}
+
+/// Threshold matrix for a screen aligned with the pixel grid (whole-pixel
+/// cell, angle a multiple of 90 degrees), indexed `row * cell + column`.
+/// Every cell then samples the same pixel positions, and symmetric shapes
+/// reach whole rings of them at once, so the growth function alone would
+/// move tone in coarse steps. Ranking the positions instead, with ties
+/// broken around the ring, inks one more pixel per cell at each step.
+fn aligned_thresholds(shape: DotShape, cell_size: f32, angle: f32) -> Option<Vec<f32>> {
+    if cell_size.fract() != 0.0 || angle.rem_euclid(90.0) != 0.0 {
+        return None;
+    }
+
+    let cell = cell_size as usize;
+    let position = |index: usize| (index as f32 + 0.5) * 2.0 / cell_size - 1.0;
+    let mut order: Vec<(usize, f32, f32)> = (0..cell * cell)
+        .map(|index| {
+            let (u, v) = (position(index % cell), position(index / cell));
+            (index, shape.coverage_at(u, v), v.atan2(u))
+        })
+        .collect();
+    order.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.2.total_cmp(&b.2)));
+
+    let mut thresholds = vec![0.0; cell * cell];
+    for (rank, (index, _, _)) in order.into_iter().enumerate() {
+        thresholds[index] = (rank as f32 + 0.5) / (cell * cell) as f32;
+    }
+    Some(thresholds)
+}
+
+/// Screens a grayscale image into black and white clustered dots.
+pub fn apply_halftone(
+    mut img: ImageBuffer<Luma<u8>, Vec<u8>>,
+    settings: &HalftoneSettings,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let (sin, cos) = settings.angle.to_radians().sin_cos();
+    let cell_size = settings.cell_size.max(2.0);
+    let cell = cell_size as usize;
+    let aligned = aligned_thresholds(settings.shape, cell_size, settings.angle);
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let sx = (px * cos + py * sin) / cell_size;
+        let sy = (-px * sin + py * cos) / cell_size;
+        let (fx, fy) = (sx - sx.floor(), sy - sy.floor());
+        let threshold = match &aligned {
+            Some(thresholds) => {
+                let column = ((fx * cell_size) as usize).min(cell - 1);
+                let row = ((fy * cell_size) as usize).min(cell - 1);
+                thresholds[row * cell + column]
+            }
+            None => settings.shape.coverage_at(fx * 2.0 - 1.0, fy * 2.0 - 1.0),
+        };
+
+        let darkness = 1.0 - pixel[0] as f32 / 255.0;
+        pixel[0] = if threshold < darkness { 0 } else { 255 };
+    }
+
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPES: [DotShape; 4] = [
+        DotShape::Round,
+        DotShape::Square,
+        DotShape::Line,
+        DotShape::Diamond,
+    ];
+
+    /// Screens a flat gray covering whole cells and returns the inked share.
+    fn inked_share(shape: DotShape, darkness: f32) -> f32 {
+        let level = (255.0 * (1.0 - darkness)).round() as u8;
+        let img = ImageBuffer::from_pixel(64, 64, Luma([level]));
+        let settings = HalftoneSettings {
+            shape,
+            cell_size: 8.0,
+            angle: 0.0,
+        };
+        let screened = apply_halftone(img, &settings);
+        let inked = screened.pixels().filter(|pixel| pixel[0] == 0).count();
+        inked as f32 / (64.0 * 64.0)
+    }
+
+    #[test]
+    fn flat_grays_ink_their_share_of_each_cell() {
+        for shape in SHAPES {
+            for darkness in [0.25, 0.5, 0.75] {
+                let share = inked_share(shape, darkness);
+                assert!(
+                    (share - darkness).abs() <= 0.02,
+                    "{:?} at {} inked {}",
+                    shape,
+                    darkness,
+                    share
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn white_and_black_stay_solid() {
+        for shape in SHAPES {
+            assert_eq!(inked_share(shape, 0.0), 0.0);
+            assert_eq!(inked_share(shape, 1.0), 1.0);
+        }
+    }
+}
@@ -1,20 +1,67 @@
 // ABOUTME: Eink image converter utility that optimizes images for eink displays
 // ABOUTME: Applies dithering, contrast enhancement, and grayscale conversion
 
-use clap::{Arg, Command};
-use image::{DynamicImage, ImageBuffer, Luma};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command};
+use image::codecs::jpeg::JpegDecoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Pixel, Rgb};
 use indicatif::{ProgressBar, ProgressStyle};
 
+mod accent;
+mod clear;
+mod delta;
+mod display;
+mod esphome;
+mod halftone;
+mod perspective;
+mod raw;
+mod rotate;
+mod slideshow;
+mod suggest;
+mod video;
+
+use accent::{AccentColor, AccentSettings};
+use display::DisplayProfile;
+use halftone::{DotShape, HalftoneSettings};
+use perspective::Corners;
+use raw::Compression;
+use slideshow::SlideshowConfig;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use video::Sampling;
+
+/// Processing settings shared by every stage of the pipeline.
+#[derive(Clone, Debug)]
+struct ProcessOptions {
+    contrast_level: f32,
+    enable_dither: bool,
+    diffusion_amount: f32,
+    tone_lut: Vec<u8>,
+    threshold: u8,
+    compression: Compression,
+    delta_from: Option<String>,
+    display: Option<DisplayProfile>,
+    esphome: bool,
+    low_memory: bool,
+    rotate_degrees: f32,
+    fill: Rgb<u8>,
+    corners: Option<Corners>,
+    halftone: Option<HalftoneSettings>,
+    accent: Option<AccentSettings>,
+}
+
 fn main() {
     let matches = Command::new("eink-image")
         .version("0.2.0")
         .about("Convert images for optimal eink display rendering")
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("input")
                 .short('i')
                 .long("input")
                 .value_name("FILE")
-                .help("Input image file")
+                .help("Input image file (or video file with the `video` feature)")
                 .required(true),
         )
         .arg(
@@ -22,7 +69,8 @@ fn main() {
                 .short('o')
                 .long("output")
                 .value_name("FILE")
-                .help("Output image file")
+                .help("Output file; repeat to write several from one pass (.bin/.raw packed buffer, .delta delta stream, else image)")
+                .action(clap::ArgAction::Append)
                 .required(true),
         )
         .arg(
@@ -31,20 +79,57 @@ fn main() {
                 .long("contrast")
                 .value_name("LEVEL")
                 .help("Contrast enhancement level (0.0-2.0)")
-                .default_value("1.3"),
+                .default_value("1.3")
+                .global(true),
         )
         .arg(
             Arg::new("no-dither")
                 .long("no-dither")
                 .help("Disable Floyd-Steinberg dithering")
-                .action(clap::ArgAction::SetTrue),
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("halftone")
+                .long("halftone")
+                .help("Use a clustered-dot halftone screen instead of Floyd-Steinberg")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("no-dither")
+                .global(true),
+        )
+        .arg(
+            Arg::new("dot-shape")
+                .long("dot-shape")
+                .value_name("SHAPE")
+                .help("Halftone dot shape")
+                .value_parser(["round", "square", "line", "diamond"])
+                .default_value("round")
+                .global(true),
+        )
+        .arg(
+            Arg::new("cell-size")
+                .long("cell-size")
+                .value_name("PIXELS")
+                .help("Halftone screen period in pixels")
+                .default_value("6")
+                .global(true),
+        )
+        .arg(
+            Arg::new("screen-angle")
+                .long("screen-angle")
+                .value_name("DEGREES")
+                .help("Halftone screen angle")
+                .allow_negative_numbers(true)
+                .default_value("45")
+                .global(true),
         )
         .arg(
             Arg::new("diffusion")
                 .long("diffusion")
                 .value_name("AMOUNT")
                 .help("Error diffusion amount (0.0-1.0)")
-                .default_value("0.8"),
+                .default_value("0.8")
+                .global(true),
         )
         .arg(
             Arg::new("gamma")
@@ -52,7 +137,40 @@ fn main() {
                 .long("gamma")
                 .value_name("GAMMA")
                 .help("Gamma correction value")
-                .default_value("2.2"),
+                .default_value("2.2")
+                .global(true),
+        )
+        .arg(
+            Arg::new("corners")
+                .long("corners")
+                .value_name("auto|x1,y1,...,x4,y4")
+                .help("Perspective-correct a photographed page: detect it, or give TL,TR,BR,BL corners")
+                .global(true),
+        )
+        .arg(
+            Arg::new("rotate")
+                .long("rotate")
+                .value_name("DEGREES")
+                .help("Rotate clockwise by any angle before fitting")
+                .allow_negative_numbers(true)
+                .default_value("0")
+                .global(true),
+        )
+        .arg(
+            Arg::new("fill")
+                .long("fill")
+                .value_name("COLOR")
+                .help("Background for rotated corners and letterboxing (white, black, #rrggbb)")
+                .default_value("white")
+                .global(true),
+        )
+        .arg(
+            Arg::new("display")
+                .short('d')
+                .long("display")
+                .value_name("NAME|FILE")
+                .help("Target display profile: fits to its resolution and applies its tone curve")
+                .global(true),
         )
         .arg(
             Arg::new("threshold")
@@ -60,12 +178,337 @@ fn main() {
                 .long("threshold")
                 .value_name("LEVEL")
                 .help("Dithering threshold (0-255)")
-                .default_value("128"),
+                .default_value("128")
+                .global(true),
+        )
+        .arg(
+            Arg::new("accent")
+                .long("accent")
+                .value_name("COLOR")
+                .help("Separate an accent ink plane for tri-color panels")
+                .value_parser(["red", "yellow"])
+                .global(true),
+        )
+        .arg(
+            Arg::new("accent-hue")
+                .long("accent-hue")
+                .value_name("START-END")
+                .help("Hue range in degrees classified as accent; wraps through 0 (red: 330-25, yellow: 40-70)")
+                .requires("accent")
+                .global(true),
+        )
+        .arg(
+            Arg::new("accent-min-sat")
+                .long("accent-min-sat")
+                .value_name("0.0-1.0")
+                .help("Minimum saturation for an accent pixel (default 0.45)")
+                .requires("accent")
+                .global(true),
+        )
+        .arg(
+            Arg::new("accent-min-val")
+                .long("accent-min-val")
+                .value_name("0.0-1.0")
+                .help("Minimum value (brightness) for an accent pixel (red: 0.25, yellow: 0.45)")
+                .requires("accent")
+                .global(true),
+        )
+        .arg(
+            Arg::new("accent-preview")
+                .long("accent-preview")
+                .value_name("FILE")
+                .help("Write an image highlighting the pixels assigned to the accent plane")
+                .requires("accent")
+                .global(true),
+        )
+        .arg(
+            Arg::new("low-memory")
+                .long("low-memory")
+                .help("Decode at reduced size and run single-threaded for constrained hosts")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .value_name("METHOD")
+                .help("Compress raw outputs into an EINK container, and .delta records (rle, deflate)")
+                .value_parser(["none", "rle", "deflate"])
+                .default_value("none")
+                .global(true),
+        )
+        .arg(
+            Arg::new("delta-from")
+                .long("delta-from")
+                .value_name("FILE")
+                .help("Previous raw frame to diff against for .delta outputs"),
+        )
+        .arg(
+            Arg::new("every")
+                .long("every")
+                .value_name("INTERVAL")
+                .help("For video input, sample one frame per interval (e.g. 10s, 500ms, 2m)")
+                .conflicts_with("frames"),
+        )
+        .arg(
+            Arg::new("frames")
+                .long("frames")
+                .value_name("COUNT")
+                .help("For video input, sample this many frames spread across the video"),
+        )
+        .arg(
+            Arg::new("esphome")
+                .long("esphome")
+                .help("Also write the matching ESPHome image: YAML snippet next to the output")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .subcommand(
+            Command::new("slideshow")
+                .about("Rotate a directory of images onto a device on an interval")
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Directory of source images")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("PATH")
+                        .help("File or device node that receives each converted image"),
+                )
+                .arg(
+                    Arg::new("push-cmd")
+                        .long("push-cmd")
+                        .value_name("COMMAND")
+                        .help("Shell command run for each image; {} is replaced by the converted file"),
+                )
+                .arg(
+                    Arg::new("dwell")
+                        .long("dwell")
+                        .value_name("INTERVAL")
+                        .help("How long each image stays on screen (e.g. 30s, 10m, 1h)")
+                        .default_value("10m"),
+                )
+                .arg(
+                    Arg::new("shuffle")
+                        .long("shuffle")
+                        .help("Shuffle the order on every pass")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("EXT")
+                        .help("Converted file format (defaults to the target's extension, else bin)")
+                        .value_parser(["bin", "raw", "png", "bmp"]),
+                )
+                .arg(
+                    Arg::new("cache")
+                        .long("cache")
+                        .value_name("DIR")
+                        .help("Directory for cached conversions"),
+                )
+                .arg(
+                    Arg::new("once")
+                        .long("once")
+                        .help("Show every image once and exit instead of looping")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("suggest")
+                .about("Analyze an image and recommend processing flags")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .long("input")
+                        .value_name("FILE")
+                        .help("Image to analyze")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("preview")
+                        .long("preview")
+                        .value_name("DIR")
+                        .help("Write previews of the top 3 candidates into this directory"),
+                ),
+        )
+        .subcommand(
+            Command::new("generate")
+                .about("Generate utility frames for a display")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("clear")
+                        .about("Ghost-clearing frames (black, white, checker, inverse checker) at the display size")
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .value_name("FILE")
+                                .help("Output file; the full sequence is numbered (frame-0001.bin, ...)")
+                                .action(clap::ArgAction::Append)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("pattern")
+                                .long("pattern")
+                                .value_name("PATTERN")
+                                .help("Write only this frame, unnumbered, instead of the full sequence")
+                                .value_parser(["black", "white", "checker", "inverse-checker"]),
+                        )
+                        .arg(
+                            Arg::new("checker-size")
+                                .long("checker-size")
+                                .value_name("PIXELS")
+                                .help("Checkerboard square size")
+                                .default_value("8"),
+                        ),
+                ),
         )
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("slideshow", slideshow_matches)) => {
+            run_slideshow(slideshow_matches);
+            return;
+        }
+        Some(("suggest", suggest_matches)) => {
+            run_suggest(suggest_matches);
+            return;
+        }
+        Some(("generate", generate_matches)) => {
+            if let Some(("clear", clear_matches)) = generate_matches.subcommand() {
+                run_generate_clear(clear_matches);
+            }
+            return;
+        }
+        _ => {}
+    }
+
     let input_path = matches.get_one::<String>("input").unwrap();
-    let output_path = matches.get_one::<String>("output").unwrap();
+    let output_paths: Vec<String> = matches
+        .get_many::<String>("output")
+        .unwrap()
+        .cloned()
+        .collect();
+    let mut options = match pipeline_options(&matches) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if options.compression != Compression::None
+        && !output_paths
+            .iter()
+            .any(|path| raw::is_raw_path(path) || delta::is_delta_path(path))
+    {
+        eprintln!("Error: --compress only applies to raw (.bin/.raw) and .delta outputs");
+        std::process::exit(1);
+    }
+
+    options.delta_from = matches.get_one::<String>("delta-from").cloned();
+    if output_paths.iter().any(|path| delta::is_delta_path(path)) && options.delta_from.is_none() {
+        eprintln!("Error: .delta outputs require --delta-from with the previous raw frame");
+        std::process::exit(1);
+    }
+
+    let sampling = if let Some(every) = matches.get_one::<String>("every") {
+        match parse_interval(every) {
+            Ok(seconds) => Some(Sampling::Every(seconds)),
+            Err(e) => {
+                eprintln!("Error: invalid --every value: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(frames) = matches.get_one::<String>("frames") {
+        match frames.parse::<u32>() {
+            Ok(count) if count > 0 => Some(Sampling::Frames(count)),
+            Ok(_) => {
+                eprintln!("Error: invalid --frames value: must be at least 1");
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: invalid --frames value: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if video::is_video_path(input_path) && sampling.is_none() {
+        eprintln!("Error: video input needs --every or --frames to pick frames");
+        std::process::exit(1);
+    }
+    if !video::is_video_path(input_path) && sampling.is_some() {
+        eprintln!("Error: --every and --frames only apply to video input");
+        std::process::exit(1);
+    }
+
+    options.esphome = matches.get_flag("esphome");
+    if options.esphome && !output_paths.iter().any(|path| is_image_path(path)) {
+        eprintln!("Error: --esphome needs an image output (e.g. .png) among the outputs");
+        std::process::exit(1);
+    }
+
+    if options.low_memory && !(is_jpeg_path(input_path) && scaled_decode_possible(&options)) {
+        eprintln!(
+            "Warning: --low-memory only shrinks the decode for JPEG input with --display \
+             and without manual --corners; {} is decoded at full size",
+            input_path
+        );
+    }
+
+    let pb = ProgressBar::new(100);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} {msg}",
+            )
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb.set_message("Processing image...");
+
+    if let Some(sampling) = sampling {
+        match process_video(input_path, &output_paths, sampling, &options, &pb) {
+            Ok(count) => {
+                pb.finish_with_message("Video processed successfully!");
+                for output_path in &output_paths {
+                    println!(
+                        "Saved {} frames starting at: {}",
+                        count,
+                        numbered_path(output_path, 1)
+                    );
+                }
+            }
+            Err(e) => {
+                pb.finish_with_message("Processing failed");
+                eprintln!("Error processing video: {}", e);
+            }
+        }
+        return;
+    }
+
+    match process_image(input_path, &output_paths, &options, &pb) {
+        Ok(_) => {
+            pb.finish_with_message("Image processed successfully!");
+            for output_path in &output_paths {
+                println!("Output saved to: {}", output_path);
+            }
+        }
+        Err(e) => {
+            pb.finish_with_message("Processing failed");
+            eprintln!("Error processing image: {}", e);
+        }
+    }
+}
+
+/// Builds the pipeline settings shared by conversion and the slideshow.
+fn pipeline_options(matches: &ArgMatches) -> Result<ProcessOptions, Box<dyn std::error::Error>> {
     let contrast_level: f32 = matches
         .get_one::<String>("contrast")
         .unwrap()
@@ -82,181 +525,765 @@ fn main() {
         .unwrap()
         .parse()
         .unwrap_or(2.2);
+    let gamma_explicit = matches.value_source("gamma") == Some(ValueSource::CommandLine);
     let threshold: u8 = matches
         .get_one::<String>("threshold")
         .unwrap()
         .parse()
         .unwrap_or(128);
+    let compression =
+        Compression::from_name(matches.get_one::<String>("compress").unwrap()).unwrap();
 
-    let pb = ProgressBar::new(100);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} {msg}",
-            )
+    let rotate_degrees: f32 = matches
+        .get_one::<String>("rotate")
+        .unwrap()
+        .parse()
+        .unwrap_or(0.0);
+    if !rotate_degrees.is_finite() {
+        return Err(format!("rotation '{}' must be a finite angle", rotate_degrees).into());
+    }
+    let fill = rotate::parse_color(matches.get_one::<String>("fill").unwrap())?;
+    let corners = matches
+        .get_one::<String>("corners")
+        .map(|spec| perspective::parse_corners(spec))
+        .transpose()?;
+    let halftone = matches.get_flag("halftone").then(|| HalftoneSettings {
+        shape: DotShape::from_name(matches.get_one::<String>("dot-shape").unwrap()).unwrap(),
+        cell_size: matches
+            .get_one::<String>("cell-size")
             .unwrap()
-            .progress_chars("#>-"),
-    );
-    pb.set_message("Processing image...");
+            .parse()
+            .unwrap_or(6.0),
+        angle: matches
+            .get_one::<String>("screen-angle")
+            .unwrap()
+            .parse()
+            .unwrap_or(45.0),
+    });
+    let accent = matches
+        .get_one::<String>("accent")
+        .map(|name| {
+            let color = AccentColor::from_name(name).unwrap();
+            let (default_hue, default_saturation, default_value) = color.default_thresholds();
+            let hue_range = matches
+                .get_one::<String>("accent-hue")
+                .map(|spec| accent::parse_hue_range(spec))
+                .transpose()?
+                .unwrap_or(default_hue);
+            let min_saturation = matches
+                .get_one::<String>("accent-min-sat")
+                .map(|spec| accent::parse_min_threshold(spec))
+                .transpose()?
+                .unwrap_or(default_saturation);
+            let min_value = matches
+                .get_one::<String>("accent-min-val")
+                .map(|spec| accent::parse_min_threshold(spec))
+                .transpose()?
+                .unwrap_or(default_value);
+            Ok::<_, Box<dyn std::error::Error>>(AccentSettings {
+                color,
+                hue_range,
+                min_saturation,
+                min_value,
+                preview: matches.get_one::<String>("accent-preview").cloned(),
+            })
+        })
+        .transpose()?;
+    let low_memory = matches.get_flag("low-memory");
+    if low_memory {
+        // Must happen before the first decode creates the global rayon pool.
+        std::env::set_var("RAYON_NUM_THREADS", "1");
+    }
 
-    match process_image(
-        input_path,
-        output_path,
+    let display = matches
+        .get_one::<String>("display")
+        .map(|d| display::resolve(d))
+        .transpose()?;
+
+    // A measured tone response is inverted and replaces the generic gamma
+    // unless --gamma is given.
+    let tone_lut = match display.as_ref().filter(|_| !gamma_explicit) {
+        Some(DisplayProfile {
+            tone_curve: Some(points),
+            ..
+        }) => display::correction_lut(points),
+        Some(DisplayProfile {
+            gamma: Some(profile_gamma),
+            ..
+        }) => gamma_lut(*profile_gamma),
+        _ => gamma_lut(gamma),
+    };
+
+    Ok(ProcessOptions {
         contrast_level,
         enable_dither,
         diffusion_amount,
-        gamma,
+        tone_lut,
         threshold,
-        &pb,
-    ) {
-        Ok(_) => {
-            pb.finish_with_message("Image processed successfully!");
-            println!("Output saved to: {}", output_path);
+        compression,
+        delta_from: None,
+        display,
+        esphome: false,
+        low_memory,
+        rotate_degrees,
+        fill,
+        corners,
+        halftone,
+        accent,
+    })
+}
+
+fn run_slideshow(matches: &ArgMatches) {
+    let options = match pipeline_options(matches) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
+    };
+
+    if options.low_memory && !scaled_decode_possible(&options) {
+        eprintln!(
+            "Warning: --low-memory only shrinks the decode with --display and without \
+             manual --corners; sources are decoded at full size"
+        );
+    }
+
+    let target = matches.get_one::<String>("target").map(PathBuf::from);
+    let push_command = matches.get_one::<String>("push-cmd").cloned();
+    if target.is_none() && push_command.is_none() {
+        eprintln!("Error: slideshow needs --target and/or --push-cmd to deliver images");
+        std::process::exit(1);
+    }
+
+    let dwell = match parse_interval(matches.get_one::<String>("dwell").unwrap()) {
+        Ok(seconds) => Duration::from_secs_f64(seconds),
         Err(e) => {
-            pb.finish_with_message("Processing failed");
-            eprintln!("Error processing image: {}", e);
+            eprintln!("Error: invalid --dwell value: {}", e);
+            std::process::exit(1);
         }
+    };
+
+    let format = matches
+        .get_one::<String>("format")
+        .cloned()
+        .unwrap_or_else(|| {
+            target
+                .as_ref()
+                .and_then(|target| target.extension())
+                .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+                .filter(|ext| image::ImageFormat::from_extension(ext).is_some())
+                .unwrap_or_else(|| "bin".to_string())
+        });
+
+    let config = SlideshowConfig {
+        source_dir: PathBuf::from(matches.get_one::<String>("dir").unwrap()),
+        target,
+        push_command,
+        dwell,
+        shuffle: matches.get_flag("shuffle"),
+        cache_dir: matches
+            .get_one::<String>("cache")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::temp_dir().join("eink-image-cache")),
+        format,
+        once: matches.get_flag("once"),
+    };
+
+    if let Err(e) = slideshow::run(&config, &options) {
+        eprintln!("Error running slideshow: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Prints the image analysis and the best-scoring flag sets, optionally
+/// rendering the top candidates so they can be compared side by side.
+fn run_suggest(matches: &ArgMatches) {
+    let options = match pipeline_options(matches) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let input_path = matches.get_one::<String>("input").unwrap();
+    let img = match load_image(input_path, &options) {
+        Ok(img) => img,
+        Err(e) => {
+            eprintln!("Error loading image: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let target = options
+        .display
+        .as_ref()
+        .map(|profile| (profile.width, profile.height));
+    let analysis = suggest::analyze(&img, target, &options.tone_lut);
+    let candidates = suggest::candidates(&analysis);
+
+    match &options.display {
+        Some(profile) => println!("Analysis of {} for {}:", input_path, profile.name),
+        None => println!("Analysis of {}:", input_path),
+    }
+    println!(
+        "  Dynamic range:  {}-{} ({:.0}%)",
+        analysis.low,
+        analysis.high,
+        analysis.range() * 100.0
+    );
+    println!("  Noise:          {:.1}", analysis.noise);
+    println!("  Text-likeness:  {:.2}", analysis.text_likeness);
+    println!(
+        "  Colorfulness:   {:.0} ({})",
+        analysis.colorfulness,
+        suggest::describe_colorfulness(analysis.colorfulness)
+    );
+
+    println!();
+    for (rank, candidate) in candidates.iter().take(3).enumerate() {
+        let heading = if rank == 0 {
+            "Recommended"
+        } else {
+            "Alternative"
+        };
+        println!("{}: {} ({})", heading, candidate.name, candidate.reason);
+        println!(
+            "  eink-image -i {} -o out.png{} {}",
+            shell_quote(input_path),
+            echoed_pipeline_flags(matches, candidate),
+            candidate.flags()
+        );
+    }
+    if analysis.red_share > 0.02 {
+        println!();
+        println!(
+            "{:.0}% of the image is saturated red; on a black/white/red panel try --accent red --accent-preview red.png",
+            analysis.red_share * 100.0
+        );
+    }
+
+    if let Some(preview_dir) = matches.get_one::<String>("preview") {
+        if let Err(e) = write_suggest_previews(&img, input_path, preview_dir, &candidates, &options)
+        {
+            eprintln!("Error writing previews: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Pipeline flags that shape the analysis and previews without being chosen
+/// by a candidate; a suggested command repeats them so it reproduces the
+/// preview it was picked from.
+const ECHOED_PIPELINE_FLAGS: &[&str] = &[
+    "display",
+    "gamma",
+    "corners",
+    "rotate",
+    "fill",
+    "threshold",
+    "accent",
+    "accent-hue",
+    "accent-min-sat",
+    "accent-min-val",
+    "low-memory",
+];
+
+/// The pipeline flags the user passed on the command line, formatted as
+/// ` --flag value` pairs.
+fn echoed_pipeline_flags(matches: &ArgMatches, candidate: &suggest::Candidate) -> String {
+    let mut flags = String::new();
+    for &id in ECHOED_PIPELINE_FLAGS {
+        if matches.value_source(id) != Some(ValueSource::CommandLine)
+            || (id == "threshold" && candidate.sets_threshold())
+        {
+            continue;
+        }
+        if id == "low-memory" {
+            flags.push_str(" --low-memory");
+        } else if let Some(value) = matches.get_one::<String>(id) {
+            flags.push_str(&format!(" --{}={}", id, shell_quote(value)));
+        }
+    }
+    flags
+}
+
+/// Single-quotes a value for the shell unless it is made of safe characters.
+fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._,:/+-=".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+fn write_suggest_previews(
+    img: &DynamicImage,
+    input_path: &str,
+    preview_dir: &str,
+    candidates: &[suggest::Candidate],
+    options: &ProcessOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(preview_dir)?;
+    let stem = Path::new(input_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".to_string());
+
+    println!();
+    for candidate in candidates.iter().take(3) {
+        let mut candidate_options = options.clone();
+        candidate.apply(&mut candidate_options);
+        let path = Path::new(preview_dir)
+            .join(format!("{}-{}.png", stem, candidate.name))
+            .to_string_lossy()
+            .into_owned();
+        convert_image(
+            img.clone(),
+            std::slice::from_ref(&path),
+            &candidate_options,
+            &ProgressBar::hidden(),
+        )?;
+        println!("Preview ({}) saved to: {}", candidate.name, path);
+    }
+
+    Ok(())
+}
+
+/// Writes ghost-clearing frames at the display's size through the normal
+/// output writers, so raw buffers match what the panel already receives.
+fn run_generate_clear(matches: &ArgMatches) {
+    let options = match pipeline_options(matches) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let Some(profile) = &options.display else {
+        eprintln!("Error: generate clear needs --display to know the panel size");
+        std::process::exit(1);
+    };
+
+    let output_paths: Vec<String> = matches
+        .get_many::<String>("output")
+        .unwrap()
+        .cloned()
+        .collect();
+    if output_paths.iter().any(|path| delta::is_delta_path(path)) {
+        eprintln!("Error: clearing frames are full refreshes; use a raw or image output");
+        std::process::exit(1);
+    }
+    let checker_size: u32 = matches
+        .get_one::<String>("checker-size")
+        .unwrap()
+        .parse()
+        .unwrap_or(8);
+    let single = matches
+        .get_one::<String>("pattern")
+        .map(|name| clear::Pattern::from_name(name).unwrap());
+    let patterns = match single {
+        Some(pattern) => vec![pattern],
+        None => clear::SEQUENCE.to_vec(),
+    };
+
+    // Tri-color buffers keep their accent plane, left empty.
+    let accent_mask = options
+        .accent
+        .as_ref()
+        .map(|_| ImageBuffer::from_pixel(profile.width, profile.height, Luma([0u8])));
+
+    let pb = ProgressBar::hidden();
+    for (index, pattern) in patterns.iter().enumerate() {
+        let frame = clear::render(*pattern, profile.width, profile.height, checker_size);
+        for output_path in &output_paths {
+            let frame_path = match single {
+                Some(_) => output_path.clone(),
+                None => numbered_path(output_path, index + 1),
+            };
+            if let Err(e) = save_output(&frame, accent_mask.as_ref(), &frame_path, &options, &pb) {
+                eprintln!("Error writing {}: {}", frame_path, e);
+                std::process::exit(1);
+            }
+            println!("{} frame saved to: {}", pattern.name(), frame_path);
+        }
+    }
+}
+
+/// Parses an interval such as `10s`, `500ms`, `2m`, `1h`, or a bare number of seconds.
+fn parse_interval(spec: &str) -> Result<f64, Box<dyn std::error::Error>> {
+    let spec = spec.trim();
+    let (number, scale) = if let Some(ms) = spec.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = spec.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = spec.strip_suffix('m') {
+        (m, 60.0)
+    } else if let Some(h) = spec.strip_suffix('h') {
+        (h, 3600.0)
+    } else {
+        (spec, 1.0)
+    };
+
+    let seconds = number.trim().parse::<f64>()? * scale;
+    if seconds <= 0.0 || !seconds.is_finite() {
+        return Err(format!("interval '{}' must be positive", spec).into());
     }
+
+    Ok(seconds)
 }
 
-#[allow(clippy::too_many_arguments)]
 fn process_image(
     input_path: &str,
-    output_path: &str,
-    contrast_level: f32,
-    enable_dither: bool,
-    diffusion_amount: f32,
-    gamma: f32,
-    threshold: u8,
+    output_paths: &[String],
+    options: &ProcessOptions,
     pb: &ProgressBar,
 ) -> Result<(), Box<dyn std::error::Error>> {
     pb.set_message("Loading image...");
-    let img = image::open(input_path)?;
+    let img = load_image(input_path, options)?;
     pb.set_position(20);
 
+    convert_image(img, output_paths, options, pb)
+}
+
+/// Decodes an input image. In low-memory mode with a display profile, JPEGs
+/// are downscaled inside the decoder (1/2, 1/4, or 1/8 IDCT scaling) to the
+/// smallest size that still covers the panel, so the full-resolution bitmap
+/// is never allocated.
+fn load_image(
+    input_path: &str,
+    options: &ProcessOptions,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    match &options.display {
+        Some(profile)
+            if options.low_memory
+                && is_jpeg_path(input_path)
+                && scaled_decode_possible(options) =>
+        {
+            let reader = std::io::BufReader::new(std::fs::File::open(input_path)?);
+            let mut decoder = JpegDecoder::new(reader)?;
+            decoder.scale(
+                profile.width.min(u16::MAX as u32) as u16,
+                profile.height.min(u16::MAX as u32) as u16,
+            )?;
+            Ok(DynamicImage::from_decoder(decoder)?)
+        }
+        _ => Ok(image::open(input_path)?),
+    }
+}
+
+/// True when the settings allow a reduced-size decode: it needs a display
+/// size to target, and manual corners are in full-resolution source pixels.
+fn scaled_decode_possible(options: &ProcessOptions) -> bool {
+    options.display.is_some() && !matches!(options.corners, Some(Corners::Manual(_)))
+}
+
+fn is_jpeg_path(path: &str) -> bool {
+    ImageFormat::from_path(path).ok() == Some(ImageFormat::Jpeg)
+}
+
+/// Samples frames from a video and converts each into numbered outputs,
+/// e.g. `frame.png` becomes `frame-0001.png`, `frame-0002.png`, ...
+fn process_video(
+    input_path: &str,
+    output_paths: &[String],
+    sampling: Sampling,
+    options: &ProcessOptions,
+    pb: &ProgressBar,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    pb.set_message("Decoding video...");
+    video::extract_frames(input_path, sampling, |index, frame| {
+        let frame_paths: Vec<String> = output_paths
+            .iter()
+            .map(|path| numbered_path(path, index + 1))
+            .collect();
+        convert_image(frame, &frame_paths, options, pb)?;
+        pb.suspend(|| println!("Frame {} saved to: {}", index + 1, frame_paths.join(", ")));
+        Ok(())
+    })
+}
+
+/// Inserts a zero-padded sequence number before the extension.
+fn numbered_path(path: &str, number: usize) -> String {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}-{:04}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}-{:04}", stem, number),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns true for outputs written as regular image files.
+fn is_image_path(path: &str) -> bool {
+    !raw::is_raw_path(path) && !delta::is_delta_path(path)
+}
+
+/// Runs a decoded image through the pipeline once and writes the result to
+/// every output, so a device buffer and its preview can never drift apart.
+fn convert_image(
+    img: DynamicImage,
+    output_paths: &[String],
+    options: &ProcessOptions,
+    pb: &ProgressBar,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let img = match &options.corners {
+        Some(corners) => {
+            pb.set_message("Correcting perspective...");
+            let quad = perspective::resolve(corners, &img)?;
+            perspective::correct(img, &quad)?
+        }
+        None => img,
+    };
+
+    let img = if options.rotate_degrees != 0.0 {
+        pb.set_message(format!("Rotating {} degrees...", options.rotate_degrees));
+        rotate::rotate(img, options.rotate_degrees, options.fill)
+    } else {
+        img
+    };
+
+    // Accent pixels are classified on the color image, before grayscale
+    // conversion throws the hue away.
+    let mut accent_mask = options.accent.as_ref().map(|settings| {
+        pb.set_message("Separating accent color...");
+        accent::classify(&img, settings)
+    });
+
     pb.set_message("Converting to grayscale...");
-    let grayscale_img = convert_to_grayscale(img);
+    let mut grayscale_img = convert_to_grayscale(img);
+    pb.set_position(30);
+
+    if let Some(profile) = &options.display {
+        pb.set_message(format!("Fitting to {}...", profile.name));
+        grayscale_img = fit_to_display(
+            grayscale_img,
+            profile.width,
+            profile.height,
+            options.fill.to_luma(),
+        );
+        accent_mask = accent_mask.map(|mask| {
+            let fitted = fit_to_display(mask, profile.width, profile.height, Luma([0]));
+            apply_simple_threshold(fitted, 128)
+        });
+    }
     pb.set_position(40);
 
     pb.set_message("Applying gamma correction...");
-    let gamma_corrected_img = apply_gamma_correction(grayscale_img, gamma);
+    let gamma_corrected_img = apply_tone_curve(grayscale_img, &options.tone_lut);
     pb.set_position(60);
 
     pb.set_message("Enhancing contrast...");
-    let enhanced_img = enhance_contrast(gamma_corrected_img, contrast_level);
+    let mut enhanced_img = enhance_contrast(gamma_corrected_img, options.contrast_level);
     pb.set_position(70);
 
-    pb.set_message(if enable_dither {
+    if let (Some(settings), Some(mask)) = (&options.accent, &accent_mask) {
+        if let Some(preview_path) = &settings.preview {
+            accent::save_preview(&enhanced_img, mask, preview_path)?;
+            pb.suspend(|| println!("Accent preview saved to: {}", preview_path));
+        }
+        enhanced_img = accent::clear_accent(enhanced_img, mask);
+    }
+
+    pb.set_message(if options.halftone.is_some() {
+        "Applying halftone screen..."
+    } else if options.enable_dither {
         "Applying Floyd-Steinberg dithering..."
     } else {
         "Applying threshold..."
     });
-    let final_img = if enable_dither {
-        apply_floyd_steinberg_dithering(enhanced_img, diffusion_amount, threshold)
+    let mut final_img = if let Some(settings) = &options.halftone {
+        halftone::apply_halftone(enhanced_img, settings)
+    } else if options.enable_dither {
+        apply_floyd_steinberg_dithering(enhanced_img, options.diffusion_amount, options.threshold)
     } else {
-        apply_simple_threshold(enhanced_img, threshold)
+        apply_simple_threshold(enhanced_img, options.threshold)
     };
+    if let Some(mask) = &accent_mask {
+        // Diffused error can darken a cleared pixel; accent ink must sit on white.
+        final_img = accent::clear_accent(final_img, mask);
+    }
     pb.set_position(90);
 
     pb.set_message("Saving output...");
-    final_img.save(output_path)?;
+    for output_path in output_paths {
+        save_output(&final_img, accent_mask.as_ref(), output_path, options, pb)?;
+    }
     pb.set_position(100);
 
     Ok(())
 }
 
+/// Writes the processed image in the format implied by the output path.
+/// With an accent plane, image outputs are rendered in color and raw
+/// buffers carry the accent plane after the black/white one.
+fn save_output(
+    final_img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    accent_mask: Option<&ImageBuffer<Luma<u8>, Vec<u8>>>,
+    output_path: &str,
+    options: &ProcessOptions,
+    pb: &ProgressBar,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if raw::is_raw_path(output_path) {
+        raw::save_raw(final_img, accent_mask, output_path, options.compression)?;
+    } else if delta::is_delta_path(output_path) {
+        let previous_path = options
+            .delta_from
+            .as_deref()
+            .ok_or("delta outputs require a previous frame")?;
+        let record_count = delta::save_delta(
+            final_img,
+            accent_mask,
+            previous_path,
+            output_path,
+            options.compression,
+        )?;
+        pb.suspend(|| println!("Delta stream: {} changed runs", record_count));
+    } else {
+        let image_type = match (accent_mask, &options.accent) {
+            (Some(mask), Some(settings)) => {
+                accent::compose(final_img, mask, settings.color).save(output_path)?;
+                "RGB24"
+            }
+            _ => {
+                final_img.save(output_path)?;
+                "BINARY"
+            }
+        };
+        if options.esphome {
+            let (width, height) = final_img.dimensions();
+            let snippet = esphome::save_snippet(output_path, width, height, image_type)?;
+            pb.suspend(|| println!("ESPHome snippet: {}", snippet.display()));
+        }
+    }
+
+    Ok(())
+}
+
 fn convert_to_grayscale(img: DynamicImage) -> ImageBuffer<Luma<u8>, Vec<u8>> {
     img.to_luma8()
 }
 
-fn enhance_contrast(
+/// Scales the image to fit inside the panel while keeping its aspect ratio,
+/// centering it on a canvas of the exact panel size filled with `fill`.
+fn fit_to_display(
     img: ImageBuffer<Luma<u8>, Vec<u8>>,
-    contrast_level: f32,
+    width: u32,
+    height: u32,
+    fill: Luma<u8>,
 ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
-    let (width, height) = img.dimensions();
-    let mut result = ImageBuffer::new(width, height);
+    let (src_width, src_height) = img.dimensions();
+    if (src_width, src_height) == (width, height) {
+        return img;
+    }
+
+    let scale = (width as f32 / src_width as f32).min(height as f32 / src_height as f32);
+    let fit_width = ((src_width as f32 * scale).round() as u32).clamp(1, width);
+    let fit_height = ((src_height as f32 * scale).round() as u32).clamp(1, height);
+    let resized = image::imageops::resize(&img, fit_width, fit_height, FilterType::Lanczos3);
+
+    let mut canvas = ImageBuffer::from_pixel(width, height, fill);
+    image::imageops::overlay(
+        &mut canvas,
+        &resized,
+        ((width - fit_width) / 2) as i64,
+        ((height - fit_height) / 2) as i64,
+    );
+
+    canvas
+}
 
-    for (x, y, pixel) in img.enumerate_pixels() {
+fn enhance_contrast(
+    mut img: ImageBuffer<Luma<u8>, Vec<u8>>,
+    contrast_level: f32,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    for pixel in img.pixels_mut() {
         let luminance = pixel[0] as f32 / 255.0;
         let enhanced = ((luminance - 0.5) * contrast_level + 0.5).clamp(0.0, 1.0);
-        let new_value = (enhanced * 255.0) as u8;
-        result.put_pixel(x, y, Luma([new_value]));
+        pixel[0] = (enhanced * 255.0) as u8;
     }
 
-    result
+    img
 }
 
-fn apply_gamma_correction(
-    img: ImageBuffer<Luma<u8>, Vec<u8>>,
-    gamma: f32,
-) -> ImageBuffer<Luma<u8>, Vec<u8>> {
-    let (width, height) = img.dimensions();
-    let mut result = ImageBuffer::new(width, height);
-
-    let gamma_lut: Vec<u8> = (0..256)
+fn gamma_lut(gamma: f32) -> Vec<u8> {
+    (0..256)
         .map(|i| {
             let normalized = i as f32 / 255.0;
             let corrected = normalized.powf(1.0 / gamma);
             (corrected * 255.0).round() as u8
         })
-        .collect();
+        .collect()
+}
 
-    for (x, y, pixel) in img.enumerate_pixels() {
-        let corrected_value = gamma_lut[pixel[0] as usize];
-        result.put_pixel(x, y, Luma([corrected_value]));
+fn apply_tone_curve(
+    mut img: ImageBuffer<Luma<u8>, Vec<u8>>,
+    tone_lut: &[u8],
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    for pixel in img.pixels_mut() {
+        pixel[0] = tone_lut[pixel[0] as usize];
     }
 
-    result
+    img
 }
 
 fn apply_simple_threshold(
-    img: ImageBuffer<Luma<u8>, Vec<u8>>,
+    mut img: ImageBuffer<Luma<u8>, Vec<u8>>,
     threshold: u8,
 ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
-    let (width, height) = img.dimensions();
-    let mut result = ImageBuffer::new(width, height);
-
-    for (x, y, pixel) in img.enumerate_pixels() {
-        let new_value = if pixel[0] >= threshold { 255 } else { 0 };
-        result.put_pixel(x, y, Luma([new_value]));
+    for pixel in img.pixels_mut() {
+        pixel[0] = if pixel[0] >= threshold { 255 } else { 0 };
     }
 
-    result
+    img
 }
 
 fn apply_floyd_steinberg_dithering(
-    img: ImageBuffer<Luma<u8>, Vec<u8>>,
+    mut img: ImageBuffer<Luma<u8>, Vec<u8>>,
     diffusion_amount: f32,
     threshold: u8,
 ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
     let (width, height) = img.dimensions();
-    let mut result = img.clone();
-    let mut error_buffer: Vec<Vec<f32>> = vec![vec![0.0; width as usize]; height as usize];
+    // Error only ever reaches the current and the next row, so two rolling
+    // rows replace a full-frame error buffer.
+    let mut row_errors = vec![0.0f32; width as usize];
+    let mut next_row_errors = vec![0.0f32; width as usize];
 
     for y in 0..height {
         for x in 0..width {
-            let pixel = result.get_pixel(x, y);
-            let old_value = pixel[0] as f32 + error_buffer[y as usize][x as usize];
+            let pixel = img.get_pixel(x, y);
+            let old_value = pixel[0] as f32 + row_errors[x as usize];
             let new_value = if old_value < threshold as f32 { 0 } else { 255 };
             let error = (old_value - new_value as f32) * diffusion_amount;
 
-            result.put_pixel(x, y, Luma([new_value]));
+            img.put_pixel(x, y, Luma([new_value]));
 
             if x + 1 < width {
-                error_buffer[y as usize][(x + 1) as usize] += error * 7.0 / 16.0;
+                row_errors[(x + 1) as usize] += error * 7.0 / 16.0;
             }
             if y + 1 < height {
                 if x > 0 {
-                    error_buffer[(y + 1) as usize][(x - 1) as usize] += error * 3.0 / 16.0;
+                    next_row_errors[(x - 1) as usize] += error * 3.0 / 16.0;
                 }
-                error_buffer[(y + 1) as usize][x as usize] += error * 5.0 / 16.0;
+                next_row_errors[x as usize] += error * 5.0 / 16.0;
                 if x + 1 < width {
-                    error_buffer[(y + 1) as usize][(x + 1) as usize] += error * 1.0 / 16.0;
+                    next_row_errors[(x + 1) as usize] += error * 1.0 / 16.0;
                 }
             }
         }
+
+        std::mem::swap(&mut row_errors, &mut next_row_errors);
+        next_row_errors.fill(0.0);
     }
 
-    result
+    img
 }
@@ -0,0 +1,274 @@
+// ABOUTME: Keystone/perspective correction for photographed documents and whiteboards
+// ABOUTME: Warps a four-corner quad to a rectangle, with an automatic page-edge detector
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+use std::collections::VecDeque;
+
+/// Longest side the page detector works at; detection does not need detail.
+const DETECT_SIZE: u32 = 512;
+/// Smallest share of the frame a detected page may cover.
+const MIN_PAGE_FRACTION: f32 = 0.2;
+
+/// Page corners in source pixels: top-left, top-right, bottom-right, bottom-left.
+pub type Quad = [(f32, f32); 4];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Corners {
+    Auto,
+    Manual(Quad),
+}
+
+/// Parses `--corners`: `auto`, or eight comma-separated numbers listing the
+/// top-left, top-right, bottom-right, and bottom-left corners as x,y pairs.
+pub fn parse_corners(spec: &str) -> Result<Corners, Box<dyn std::error::Error>> {
+    if spec.eq_ignore_ascii_case("auto") {
+        return Ok(Corners::Auto);
+    }
+
+    let values = spec
+        .split(',')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.len() != 8 {
+        return Err("corners need 8 numbers: x1,y1,x2,y2,x3,y3,x4,y4 (TL, TR, BR, BL)".into());
+    }
+
+    Ok(Corners::Manual([
+        (values[0], values[1]),
+        (values[2], values[3]),
+        (values[4], values[5]),
+        (values[6], values[7]),
+    ]))
+}
+
+/// Resolves the corners to use for an image, running the detector for `Auto`.
+pub fn resolve(corners: &Corners, img: &DynamicImage) -> Result<Quad, Box<dyn std::error::Error>> {
+    match corners {
+        Corners::Auto => detect_page(img),
+        Corners::Manual(quad) => Ok(*quad),
+    }
+}
+
+/// Finds a page as the largest bright region after an Otsu threshold and
+/// takes its extreme points along the diagonals as the corners. Works for
+/// paper, receipts, and whiteboards shot against a darker background.
+pub fn detect_page(img: &DynamicImage) -> Result<Quad, Box<dyn std::error::Error>> {
+    let small = img
+        .resize(DETECT_SIZE, DETECT_SIZE, FilterType::Triangle)
+        .to_luma8();
+    let scale_x = img.width() as f32 / small.width() as f32;
+    let scale_y = img.height() as f32 / small.height() as f32;
+
+    let threshold = otsu_threshold(&small);
+    let region = largest_bright_region(&small, threshold);
+    let frame_area = (small.width() * small.height()) as f32;
+    if (region.len() as f32) < frame_area * MIN_PAGE_FRACTION {
+        return Err("could not find the page edges; pass --corners explicitly".into());
+    }
+
+    let pick = |key: fn(f32, f32) -> f32| {
+        region
+            .iter()
+            .map(|&(x, y)| (x as f32 + 0.5, y as f32 + 0.5))
+            .max_by(|a, b| key(a.0, a.1).total_cmp(&key(b.0, b.1)))
+            .map(|(x, y)| (x * scale_x, y * scale_y))
+            .unwrap_or((0.0, 0.0))
+    };
+
+    Ok([
+        pick(|x, y| -(x + y)),
+        pick(|x, y| x - y),
+        pick(|x, y| x + y),
+        pick(|x, y| y - x),
+    ])
+}
+
+/// Picks the level that best splits the histogram into dark and bright classes.
+pub fn otsu_threshold(img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+
+    let total = (img.width() * img.height()) as f64;
+    let total_sum: f64 = (0..256).map(|i| i as f64 * histogram[i] as f64).sum();
+    let (mut background_weight, mut background_sum) = (0.0, 0.0);
+    let (mut best_threshold, mut best_variance) = (128u8, 0.0);
+
+    for (level, &count) in histogram.iter().enumerate() {
+        background_weight += count as f64;
+        background_sum += level as f64 * count as f64;
+        let foreground_weight = total - background_weight;
+        if background_weight == 0.0 || foreground_weight == 0.0 {
+            continue;
+        }
+
+        let background_mean = background_sum / background_weight;
+        let foreground_mean = (total_sum - background_sum) / foreground_weight;
+        let variance =
+            background_weight * foreground_weight * (background_mean - foreground_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = level as u8;
+        }
+    }
+
+    best_threshold
+}
+
+/// Returns the pixels of the largest 4-connected region brighter than `threshold`.
+fn largest_bright_region(img: &GrayImage, threshold: u8) -> Vec<(u32, u32)> {
+    let (width, height) = img.dimensions();
+    let mut visited = vec![false; (width * height) as usize];
+    let mut largest = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for start in 0..(width * height) {
+        let (sx, sy) = (start % width, start / width);
+        if visited[start as usize] || img.get_pixel(sx, sy)[0] <= threshold {
+            continue;
+        }
+
+        let mut region = Vec::new();
+        visited[start as usize] = true;
+        queue.push_back((sx, sy));
+        while let Some((x, y)) = queue.pop_front() {
+            region.push((x, y));
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbors {
+                if nx >= width || ny >= height {
+                    continue;
+                }
+                let index = (ny * width + nx) as usize;
+                if !visited[index] && img.get_pixel(nx, ny)[0] > threshold {
+                    visited[index] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+
+    largest
+}
+
+/// Warps the quad to an upright rectangle sized by its longest opposite edges,
+/// scaled down if needed so it never exceeds the source image.
+pub fn correct(img: DynamicImage, quad: &Quad) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let (src_width, src_height) = (img.width() as f32, img.height() as f32);
+    for &(x, y) in quad {
+        if !x.is_finite() || !y.is_finite() || x < 0.0 || y < 0.0 || x > src_width || y > src_height
+        {
+            return Err(format!(
+                "corner ({}, {}) is outside the {}x{} source image",
+                x,
+                y,
+                img.width(),
+                img.height()
+            )
+            .into());
+        }
+    }
+
+    let distance =
+        |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let [top_left, top_right, bottom_right, bottom_left] = *quad;
+    let edge_width = distance(top_left, top_right).max(distance(bottom_left, bottom_right));
+    let edge_height = distance(top_left, bottom_left).max(distance(top_right, bottom_right));
+    let scale = (src_width / edge_width)
+        .min(src_height / edge_height)
+        .min(1.0);
+    let width = (edge_width * scale).round() as u32;
+    let height = (edge_height * scale).round() as u32;
+    if width < 2 || height < 2 {
+        return Err("corners are too close together".into());
+    }
+
+    let target = [
+        (0.0, 0.0),
+        (width as f32, 0.0),
+        (width as f32, height as f32),
+        (0.0, height as f32),
+    ];
+    let homography = solve_homography(&target, quad).ok_or("corners must form a quadrilateral")?;
+
+    let src = img.to_rgb8();
+    let mut result = RgbImage::new(width, height);
+    for (x, y, pixel) in result.enumerate_pixels_mut() {
+        let (u, v) = (x as f64 + 0.5, y as f64 + 0.5);
+        let w = homography[6] * u + homography[7] * v + 1.0;
+        let sx = (homography[0] * u + homography[1] * v + homography[2]) / w;
+        let sy = (homography[3] * u + homography[4] * v + homography[5]) / w;
+        *pixel = sample_bilinear(&src, sx as f32 - 0.5, sy as f32 - 0.5);
+    }
+
+    Ok(DynamicImage::ImageRgb8(result))
+}
+
+/// Solves for the 3x3 homography (h22 = 1) mapping each `from` point onto
+/// the matching `to` point, by Gaussian elimination on the 8x8 system.
+fn solve_homography(from: &Quad, to: &Quad) -> Option<[f64; 8]> {
+    let mut system = [[0.0f64; 9]; 8];
+    for (i, (&(u, v), &(x, y))) in from.iter().zip(to.iter()).enumerate() {
+        let (u, v, x, y) = (u as f64, v as f64, x as f64, y as f64);
+        system[2 * i] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x, x];
+        system[2 * i + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y, y];
+    }
+
+    for column in 0..8 {
+        let pivot = (column..8)
+            .max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))?;
+        if system[pivot][column].abs() < 1e-9 {
+            return None;
+        }
+        system.swap(column, pivot);
+
+        let pivot_row = system[column];
+        for (index, row) in system.iter_mut().enumerate() {
+            if index != column {
+                let factor = row[column] / pivot_row[column];
+                for (value, pivot_value) in row.iter_mut().zip(pivot_row.iter()).skip(column) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+
+    let mut solution = [0.0; 8];
+    for (i, value) in solution.iter_mut().enumerate() {
+        *value = system[i][8] / system[i][i];
+    }
+    Some(solution)
+}
+
+fn sample_bilinear(src: &RgbImage, x: f32, y: f32) -> Rgb<u8> {
+    let max_x = src.width() as f32 - 1.0;
+    let max_y = src.height() as f32 - 1.0;
+    let x = x.clamp(0.0, max_x);
+    let y = y.clamp(0.0, max_y);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(max_x as u32), (y0 + 1).min(max_y as u32));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let p00 = src.get_pixel(x0, y0);
+    let p10 = src.get_pixel(x1, y0);
+    let p01 = src.get_pixel(x0, y1);
+    let p11 = src.get_pixel(x1, y1);
+
+    let mut out = [0u8; 3];
+    for (channel, value) in out.iter_mut().enumerate() {
+        let top = p00[channel] as f32 * (1.0 - fx) + p10[channel] as f32 * fx;
+        let bottom = p01[channel] as f32 * (1.0 - fx) + p11[channel] as f32 * fx;
+        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    Rgb(out)
+}
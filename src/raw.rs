@@ -0,0 +1,390 @@
+// ABOUTME: Raw framebuffer export for eink controllers
+// ABOUTME: Packs 1-bit buffers and wraps them in an optionally compressed container
+
+use image::{ImageBuffer, Luma};
+use std::io::{Read, Write};
+
+/// Magic bytes at the start of every compressed container.
+pub const CONTAINER_MAGIC: &[u8; 4] = b"EINK";
+/// Container layout version written after the magic.
+pub const CONTAINER_VERSION: u8 = 1;
+/// Size in bytes of the fixed container header.
+pub const CONTAINER_HEADER_LEN: usize = 18;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Rle,
+    Deflate,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::None),
+            "rle" => Some(Compression::Rle),
+            "deflate" => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Rle => 1,
+            Compression::Deflate => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Rle),
+            2 => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true when the output path should be written as a raw device buffer.
+pub fn is_raw_path(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    lower.ends_with(".bin") || lower.ends_with(".raw")
+}
+
+/// Packs a black/white image into 1 bit per pixel, MSB first, rows padded to
+/// whole bytes. Set bits are white, matching common EPD controller RAM.
+pub fn pack_1bit(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<u8> {
+    let (width, height) = img.dimensions();
+    let stride = ((width + 7) / 8) as usize;
+    let mut packed = vec![0u8; stride * height as usize];
+
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel[0] >= 128 {
+            packed[y as usize * stride + (x / 8) as usize] |= 0x80 >> (x % 8);
+        }
+    }
+
+    packed
+}
+
+/// Packs the black/white plane, followed by the accent plane when present.
+/// In the accent plane a set bit means accent ink, as tri-color controllers
+/// expect in their second RAM bank.
+pub fn pack_planes(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    accent: Option<&ImageBuffer<Luma<u8>, Vec<u8>>>,
+) -> Vec<u8> {
+    let mut packed = pack_1bit(img);
+    if let Some(mask) = accent {
+        packed.extend(pack_1bit(mask));
+    }
+    packed
+}
+
+/// PackBits run-length encoding: a control byte `n` in 0..=127 is followed by
+/// `n + 1` literal bytes, and `n` in 129..=255 repeats the next byte `257 - n`
+/// times.
+pub fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 4);
+    let mut i = 0;
+
+    while i < data.len() {
+        let mut run = 1;
+        while i + run < data.len() && run < 128 && data[i + run] == data[i] {
+            run += 1;
+        }
+
+        if run >= 2 {
+            out.push((257 - run) as u8);
+            out.push(data[i]);
+            i += run;
+            continue;
+        }
+
+        let start = i;
+        while i < data.len() && i - start < 128 {
+            if i + 1 < data.len() && data[i] == data[i + 1] {
+                break;
+            }
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&data[start..i]);
+    }
+
+    out
+}
+
+/// Reverses `rle_encode`.
+pub fn rle_decode(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut out = Vec::with_capacity(data.len() * 4);
+    let mut i = 0;
+
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+        if control < 128 {
+            let end = i + control + 1;
+            let literal = data.get(i..end).ok_or("truncated RLE literal run")?;
+            out.extend_from_slice(literal);
+            i = end;
+        } else if control > 128 {
+            let value = *data.get(i).ok_or("truncated RLE repeat run")?;
+            out.extend(std::iter::repeat(value).take(257 - control));
+            i += 1;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Compresses a packed buffer with the requested method.
+pub fn compress(data: &[u8], compression: Compression) -> std::io::Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Rle => Ok(rle_encode(data)),
+        Compression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Reverses `compress`.
+pub fn decompress(
+    data: &[u8],
+    compression: Compression,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Rle => rle_decode(data),
+        Compression::Deflate => {
+            let mut out = Vec::new();
+            flate2::read::DeflateDecoder::new(data).read_to_end(&mut out)?;
+            Ok(out)
+        }
+    }
+}
+
+/// Wraps a packed buffer in the documented container: magic, version,
+/// compression id, width, height, unpacked length, payload length, payload.
+/// All multi-byte fields are little-endian.
+pub fn encode_container(
+    packed: &[u8],
+    width: u32,
+    height: u32,
+    compression: Compression,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let width = u16::try_from(width).map_err(|_| "image too wide for container")?;
+    let height = u16::try_from(height).map_err(|_| "image too tall for container")?;
+    let payload = compress(packed, compression)?;
+
+    let mut out = Vec::with_capacity(CONTAINER_HEADER_LEN + payload.len());
+    out.extend_from_slice(CONTAINER_MAGIC);
+    out.push(CONTAINER_VERSION);
+    out.push(compression.id());
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&payload);
+
+    Ok(out)
+}
+
+/// Reads a container header, returning `None` unless the magic, version,
+/// compression id, and unpacked length all agree. The length must cover one
+/// or two 1-bit planes of the stated size, so a bare buffer whose pixels
+/// happen to spell the magic is not mistaken for a container.
+fn container_header(bytes: &[u8]) -> Option<(Compression, usize)> {
+    if !bytes.starts_with(CONTAINER_MAGIC) || bytes.len() < CONTAINER_HEADER_LEN {
+        return None;
+    }
+    if bytes[4] != CONTAINER_VERSION {
+        return None;
+    }
+    let compression = Compression::from_id(bytes[5])?;
+    let width = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+    let height = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let unpacked_len = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]) as usize;
+    let plane_len = (width + 7) / 8 * height;
+    if unpacked_len != plane_len && unpacked_len != plane_len * 2 {
+        return None;
+    }
+    Some((compression, unpacked_len))
+}
+
+/// Extracts the packed buffer from a container, returning `None` when the
+/// bytes are not a container (i.e. a bare buffer).
+pub fn decode_container(bytes: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let Some((compression, unpacked_len)) = container_header(bytes) else {
+        return Ok(None);
+    };
+    let payload_len = u32::from_le_bytes(bytes[14..18].try_into()?) as usize;
+    let payload = bytes
+        .get(CONTAINER_HEADER_LEN..CONTAINER_HEADER_LEN + payload_len)
+        .ok_or("truncated container payload")?;
+
+    let packed = decompress(payload, compression)?;
+    if packed.len() != unpacked_len {
+        return Err("container length does not match its payload".into());
+    }
+
+    Ok(Some(packed))
+}
+
+/// Reads a raw device buffer written by `save_raw`, bare or in a container.
+pub fn load_raw(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path)?;
+    match decode_container(&bytes)? {
+        Some(packed) => Ok(packed),
+        None => Ok(bytes),
+    }
+}
+
+/// Writes a black/white image as a raw device buffer. Uncompressed buffers
+/// are written bare so they can be copied straight into controller RAM.
+pub fn save_raw(
+    img: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    accent: Option<&ImageBuffer<Luma<u8>, Vec<u8>>>,
+    path: &str,
+    compression: Compression,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let packed = pack_planes(img, accent);
+    let bytes = if compression == Compression::None {
+        packed
+    } else {
+        let (width, height) = img.dimensions();
+        encode_container(&packed, width, height, compression)?
+    };
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rle_round_trip(data: &[u8]) -> Vec<u8> {
+        let encoded = rle_encode(data);
+        assert_eq!(rle_decode(&encoded).unwrap(), data);
+        encoded
+    }
+
+    #[test]
+    fn rle_empty_input() {
+        assert!(rle_round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn rle_run_of_128_is_one_repeat() {
+        let encoded = rle_round_trip(&[0xAA; 128]);
+        assert_eq!(encoded, vec![129, 0xAA]);
+    }
+
+    #[test]
+    fn rle_run_of_129_splits() {
+        let encoded = rle_round_trip(&[0x55; 129]);
+        assert_eq!(encoded, vec![129, 0x55, 0, 0x55]);
+    }
+
+    #[test]
+    fn rle_literals_at_the_128_byte_limit() {
+        let literal: Vec<u8> = (0..128).map(|i| (i * 2) as u8).collect();
+        let encoded = rle_round_trip(&literal);
+        assert_eq!(encoded[0], 127);
+        assert_eq!(encoded.len(), 129);
+
+        let longer: Vec<u8> = (0..129).map(|i| (i * 2) as u8).collect();
+        let encoded = rle_round_trip(&longer);
+        assert_eq!(encoded[0], 127);
+        assert_eq!(encoded[129], 0);
+        assert_eq!(encoded.len(), 131);
+    }
+
+    #[test]
+    fn rle_mixed_runs_and_literals() {
+        let mut data = vec![1, 2, 3];
+        data.extend([7; 300]);
+        data.extend([4, 5, 5, 6]);
+        rle_round_trip(&data);
+    }
+
+    #[test]
+    fn rle_decode_rejects_truncated_input() {
+        assert!(rle_decode(&[3, 1, 2]).is_err());
+        assert!(rle_decode(&[200]).is_err());
+    }
+
+    #[test]
+    fn container_round_trips_both_compressions() {
+        let mut packed = vec![0xFF; 500];
+        packed.extend((0..=255).collect::<Vec<u8>>());
+        packed.resize(800 / 8 * 480, 0x55);
+        for compression in [Compression::Rle, Compression::Deflate] {
+            let bytes = encode_container(&packed, 800, 480, compression).unwrap();
+            assert_eq!(&bytes[0..4], CONTAINER_MAGIC);
+            assert_eq!(bytes[4], CONTAINER_VERSION);
+            assert_eq!(bytes[5], compression.id());
+            assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 800);
+            assert_eq!(u16::from_le_bytes([bytes[8], bytes[9]]), 480);
+            assert_eq!(decode_container(&bytes).unwrap(), Some(packed.clone()));
+        }
+    }
+
+    #[test]
+    fn bare_buffer_is_not_a_container() {
+        assert_eq!(decode_container(&[0u8; 64]).unwrap(), None);
+    }
+
+    #[test]
+    fn two_plane_container_round_trips() {
+        let packed: Vec<u8> = (0..64).collect();
+        let bytes = encode_container(&packed, 13, 16, Compression::Rle).unwrap();
+        assert_eq!(decode_container(&bytes).unwrap(), Some(packed));
+    }
+
+    #[test]
+    fn bare_buffer_starting_with_magic_is_not_a_container() {
+        let mut bare = vec![0u8; 64];
+        bare[..4].copy_from_slice(CONTAINER_MAGIC);
+        assert_eq!(decode_container(&bare).unwrap(), None);
+
+        let bytes = encode_container(&[0u8; 32], 16, 16, Compression::Rle).unwrap();
+        assert_eq!(
+            decode_container(&bytes[..CONTAINER_HEADER_LEN - 1]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn container_rejects_truncated_payload() {
+        let bytes = encode_container(&[1, 2, 3, 4, 5], 8, 5, Compression::Deflate).unwrap();
+        assert!(decode_container(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn inconsistent_header_is_treated_as_bare() {
+        let bytes = encode_container(&[0u8; 32], 16, 16, Compression::Rle).unwrap();
+        let mut bad_version = bytes.clone();
+        bad_version[4] = CONTAINER_VERSION + 1;
+        let mut bad_compression = bytes.clone();
+        bad_compression[5] = 9;
+        let mut bad_length = bytes.clone();
+        bad_length[10] = 33;
+        for bytes in [bad_version, bad_compression, bad_length] {
+            assert_eq!(decode_container(&bytes).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn container_rejects_payload_length_mismatch() {
+        let mut bytes = encode_container(&[0u8; 32], 16, 16, Compression::None).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        bytes[14] = 31;
+        assert!(decode_container(&bytes).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+// ABOUTME: Arbitrary-angle rotation with background fill for tilted layouts
+// ABOUTME: Resamples with a bicubic kernel onto an enlarged canvas before fitting
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Parses a fill color: `white`, `black`, or hex `#rrggbb` / `rrggbb`.
+pub fn parse_color(spec: &str) -> Result<Rgb<u8>, Box<dyn std::error::Error>> {
+    match spec.to_ascii_lowercase().as_str() {
+        "white" => return Ok(Rgb([255, 255, 255])),
+        "black" => return Ok(Rgb([0, 0, 0])),
+        _ => {}
+    }
+
+    let hex = spec.trim_start_matches('#');
+    // Check the digits before slicing: byte indexes into non-ASCII input panic.
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("color '{}' must be white, black, or #rrggbb", spec).into());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+    Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+/// Rotates clockwise by `degrees`. Right angles are exact; other angles grow
+/// the canvas to hold the whole rotated image and fill the uncovered corners.
+pub fn rotate(img: DynamicImage, degrees: f32, fill: Rgb<u8>) -> DynamicImage {
+    let normalized = degrees.rem_euclid(360.0);
+    // rem_euclid can return exactly 360.0 for tiny negative angles, so count
+    // quarter turns modulo 4 instead of matching on the angle itself.
+    let quarter_turns = (normalized / 90.0).round();
+    if (normalized - quarter_turns * 90.0).abs() < f32::EPSILON {
+        return match (quarter_turns as i32).rem_euclid(4) {
+            0 => img,
+            1 => img.rotate90(),
+            2 => img.rotate180(),
+            _ => img.rotate270(),
+        };
+    }
+
+    DynamicImage::ImageRgb8(rotate_bicubic(&img.to_rgb8(), normalized, fill))
+}
+
+fn rotate_bicubic(src: &RgbImage, degrees: f32, fill: Rgb<u8>) -> RgbImage {
+    let (src_width, src_height) = src.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let width = (src_width as f32 * cos.abs() + src_height as f32 * sin.abs()).ceil() as u32;
+    let height = (src_width as f32 * sin.abs() + src_height as f32 * cos.abs()).ceil() as u32;
+
+    let src_cx = src_width as f32 / 2.0;
+    let src_cy = src_height as f32 / 2.0;
+    let cx = width as f32 / 2.0;
+    let cy = height as f32 / 2.0;
+
+    let mut result = RgbImage::new(width, height);
+    for (x, y, pixel) in result.enumerate_pixels_mut() {
+        // Map the destination pixel center back into the source image.
+        let dx = x as f32 + 0.5 - cx;
+        let dy = y as f32 + 0.5 - cy;
+        let sx = dx * cos + dy * sin + src_cx - 0.5;
+        let sy = -dx * sin + dy * cos + src_cy - 0.5;
+        *pixel = sample_bicubic(src, sx, sy, fill);
+    }
+
+    result
+}
+
+/// Catmull-Rom bicubic sample. Taps outside the source read the fill color,
+/// which also anti-aliases the rotated edges against the background.
+fn sample_bicubic(src: &RgbImage, x: f32, y: f32, fill: Rgb<u8>) -> Rgb<u8> {
+    let (width, height) = src.dimensions();
+    if x < -2.0 || y < -2.0 || x > width as f32 + 1.0 || y > height as f32 + 1.0 {
+        return fill;
+    }
+
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let wx = cubic_weights(x - x0);
+    let wy = cubic_weights(y - y0);
+
+    let mut sum = [0.0f32; 3];
+    for (j, weight_y) in wy.iter().enumerate() {
+        let sy = y0 as i64 + j as i64 - 1;
+        for (i, weight_x) in wx.iter().enumerate() {
+            let sx = x0 as i64 + i as i64 - 1;
+            let tap = if sx >= 0 && sy >= 0 && sx < width as i64 && sy < height as i64 {
+                *src.get_pixel(sx as u32, sy as u32)
+            } else {
+                fill
+            };
+            let weight = weight_x * weight_y;
+            for channel in 0..3 {
+                sum[channel] += tap[channel] as f32 * weight;
+            }
+        }
+    }
+
+    Rgb(sum.map(|value| value.round().clamp(0.0, 255.0) as u8))
+}
+
+fn cubic_weights(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.0,
+        -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2,
+    ]
+}
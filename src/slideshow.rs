@@ -0,0 +1,204 @@
+// ABOUTME: Slideshow daemon that rotates a directory of images onto a device
+// ABOUTME: Converts each source once into a cache, then pushes it on a dwell interval
+
+use crate::{convert_image, load_image, ProcessOptions};
+use indicatif::ProgressBar;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Everything the daemon needs besides the pipeline settings.
+#[derive(Clone, Debug)]
+pub struct SlideshowConfig {
+    pub source_dir: PathBuf,
+    pub target: Option<PathBuf>,
+    pub push_command: Option<String>,
+    pub dwell: Duration,
+    pub shuffle: bool,
+    pub cache_dir: PathBuf,
+    pub format: String,
+    pub once: bool,
+}
+
+/// Cycles through the source directory forever (or once with `once`),
+/// rescanning it on every pass so new photos are picked up. Only the first
+/// scan is fatal; later the directory may be briefly empty or unreadable
+/// while a sync client swaps files, so the daemon waits and rescans.
+pub fn run(
+    config: &SlideshowConfig,
+    options: &ProcessOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cache_dir = namespaced_cache_dir(config, options);
+    std::fs::create_dir_all(&cache_dir)?;
+    let mut rng_state = seed();
+    let mut first_pass = true;
+
+    loop {
+        let mut sources = match list_sources(&config.source_dir) {
+            Ok(sources) => sources,
+            Err(e) if first_pass => return Err(e),
+            Err(e) => {
+                eprintln!("Rescanning after {:?}: {}", config.dwell, e);
+                std::thread::sleep(config.dwell);
+                continue;
+            }
+        };
+        if config.shuffle {
+            shuffle(&mut sources, &mut rng_state);
+        }
+
+        let mut used = HashSet::new();
+        for (index, source) in sources.iter().enumerate() {
+            let shown = cached_conversion(source, &cache_dir, config, options).and_then(|path| {
+                used.insert(path.clone());
+                push(&path, config)
+            });
+            match shown {
+                Ok(()) => println!("Showing {}", source.display()),
+                // A single unreadable photo should not take the frame down.
+                Err(e) => eprintln!("Skipping {}: {}", source.display(), e),
+            }
+
+            if config.once && index + 1 == sources.len() {
+                break;
+            }
+            std::thread::sleep(config.dwell);
+        }
+
+        prune_cache(&cache_dir, &config.format, &used);
+        if config.once {
+            return Ok(());
+        }
+        first_pass = false;
+    }
+}
+
+/// Lists decodable images in the directory, sorted by name. An empty
+/// directory is an error.
+fn list_sources(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut sources: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && image::ImageFormat::from_path(path).is_ok())
+        .collect();
+    if sources.is_empty() {
+        return Err(format!("no images found in {}", dir.display()).into());
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+/// Gives each daemon configuration its own subdirectory of the cache, so
+/// daemons sharing the default cache never prune each other's entries.
+fn namespaced_cache_dir(config: &SlideshowConfig, options: &ProcessOptions) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    config.source_dir.hash(&mut hasher);
+    config.format.hash(&mut hasher);
+    format!("{:?}", options).hash(&mut hasher);
+    config.cache_dir.join(format!("{:016x}", hasher.finish()))
+}
+
+/// Removes cached conversions that were not used on the last pass, left
+/// behind when a photo was edited or deleted. Only files this daemon could
+/// have written (a 16-digit hex name with the configured format's
+/// extension) are touched.
+fn prune_cache(cache_dir: &Path, format: &str, used: &HashSet<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let is_cache_entry = path.extension().is_some_and(|ext| ext == format)
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| {
+                    stem.len() == 16 && stem.chars().all(|c| c.is_ascii_hexdigit())
+                });
+        if is_cache_entry && path.is_file() && !used.contains(&path) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                eprintln!("Could not prune {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Returns the converted file for a source, converting it only when the
+/// source changed since it was cached. The settings are covered by the
+/// namespaced `cache_dir`.
+fn cached_conversion(
+    source: &Path,
+    cache_dir: &Path,
+    config: &SlideshowConfig,
+    options: &ProcessOptions,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let metadata = std::fs::metadata(source)?;
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified()?.hash(&mut hasher);
+
+    let cached = cache_dir.join(format!("{:016x}.{}", hasher.finish(), config.format));
+    if !cached.exists() {
+        let source_str = source.to_str().ok_or("source path is not valid UTF-8")?;
+        let cached_str = cached.to_str().ok_or("cache path is not valid UTF-8")?;
+        let img = load_image(source_str, options)?;
+        convert_image(
+            img,
+            &[cached_str.to_string()],
+            options,
+            &ProgressBar::hidden(),
+        )?;
+    }
+
+    Ok(cached)
+}
+
+/// Delivers a converted file to the configured target and/or push command.
+fn push(converted: &Path, config: &SlideshowConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(target) = &config.target {
+        let bytes = std::fs::read(converted)?;
+        if target.exists() && !target.is_file() {
+            // Device nodes and pipes take the buffer as a single write.
+            std::fs::write(target, bytes)?;
+        } else {
+            // Regular files are replaced atomically so watchers never see a partial frame.
+            let staging = target.with_extension("eink-tmp");
+            std::fs::write(&staging, bytes)?;
+            std::fs::rename(&staging, target)?;
+        }
+    }
+
+    if let Some(command) = &config.push_command {
+        let path = converted.to_string_lossy();
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command.replace("{}", &path))
+            .env("EINK_IMAGE", path.as_ref())
+            .status()?;
+        if !status.success() {
+            return Err(format!("push command exited with {}", status).into());
+        }
+    }
+
+    Ok(())
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    nanos | 1
+}
+
+/// Fisher-Yates shuffle driven by xorshift64; good enough for photo order.
+fn shuffle(items: &mut [PathBuf], state: &mut u64) {
+    for i in (1..items.len()).rev() {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        let j = (*state % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
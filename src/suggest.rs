@@ -0,0 +1,373 @@
+// ABOUTME: Image analysis that recommends processing settings for a photo
+// ABOUTME: Scores preset candidates from dynamic range, noise, text-likeness, and colorfulness
+
+use crate::halftone::{DotShape, HalftoneSettings};
+use crate::ProcessOptions;
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage};
+
+/// Longest side analyzed when no display profile gives a target size.
+const ANALYSIS_SIZE: u32 = 800;
+
+/// Measurements taken on the image at the size it will be displayed.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    /// 2nd and 98th luma percentiles.
+    pub low: u8,
+    pub high: u8,
+    /// Estimated noise standard deviation in luma levels.
+    pub noise: f32,
+    /// 0.0 for photographs, 1.0 for clean text and line art.
+    pub text_likeness: f32,
+    /// Hasler-Suesstrunk colorfulness; above ~50 is noticeably colorful.
+    pub colorfulness: f32,
+    /// Share of pixels saturated enough to separate as a red accent.
+    pub red_share: f32,
+    /// Threshold separating ink from paper, used by the text candidate.
+    pub ink_threshold: u8,
+}
+
+impl Analysis {
+    /// Dynamic range as a fraction of the full 0-255 scale.
+    pub fn range(&self) -> f32 {
+        (self.high as f32 - self.low as f32) / 255.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Dither,
+    Threshold(u8),
+    Halftone,
+}
+
+/// A named set of flags with a score for how well it suits the image.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub name: &'static str,
+    pub score: f32,
+    pub reason: String,
+    pub contrast: f32,
+    pub diffusion: f32,
+    pub mode: Mode,
+}
+
+impl Candidate {
+    /// The command-line flags that select this candidate.
+    pub fn flags(&self) -> String {
+        match self.mode {
+            Mode::Dither => format!(
+                "--contrast {:.1} --diffusion {:.1}",
+                self.contrast, self.diffusion
+            ),
+            Mode::Threshold(threshold) => format!(
+                "--contrast {:.1} --no-dither --threshold {}",
+                self.contrast, threshold
+            ),
+            Mode::Halftone => format!(
+                "--contrast {:.1} --halftone --dot-shape round --cell-size 6 --screen-angle 45",
+                self.contrast
+            ),
+        }
+    }
+
+    /// True when the candidate picks its own `--threshold`.
+    pub fn sets_threshold(&self) -> bool {
+        matches!(self.mode, Mode::Threshold(_))
+    }
+
+    /// Applies the candidate's settings on top of the shared pipeline options.
+    pub fn apply(&self, options: &mut ProcessOptions) {
+        options.contrast_level = self.contrast;
+        options.diffusion_amount = self.diffusion;
+        options.enable_dither = true;
+        options.halftone = None;
+        match self.mode {
+            Mode::Dither => {}
+            Mode::Threshold(threshold) => {
+                options.enable_dither = false;
+                options.threshold = threshold;
+            }
+            Mode::Halftone => {
+                options.halftone = Some(HalftoneSettings {
+                    shape: DotShape::Round,
+                    cell_size: 6.0,
+                    angle: 45.0,
+                });
+            }
+        }
+    }
+}
+
+/// Measures the image after scaling it to the display (or a preview size),
+/// since noise and fine text look very different once downscaled. The ink
+/// threshold is found after `tone_lut`, where the threshold stage applies.
+pub fn analyze(img: &DynamicImage, target: Option<(u32, u32)>, tone_lut: &[u8]) -> Analysis {
+    let (width, height) = target.unwrap_or((ANALYSIS_SIZE, ANALYSIS_SIZE));
+    let scaled = if img.width() > width || img.height() > height {
+        img.resize(width, height, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let luma = scaled.to_luma8();
+    let rgb = scaled.to_rgb8();
+
+    let mut histogram = [0u64; 256];
+    for pixel in luma.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total = (luma.width() * luma.height()) as u64;
+    let percentile = |fraction: f64| {
+        let target = (total as f64 * fraction) as u64;
+        let mut seen = 0;
+        for (level, &count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen > target {
+                return level as u8;
+            }
+        }
+        255
+    };
+
+    let (mut sum_rg, mut sum_yb, mut sum_rg2, mut sum_yb2) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    let mut red_pixels = 0u64;
+    for pixel in rgb.pixels() {
+        let [r, g, b] = pixel.0.map(|channel| channel as f64);
+        let rg = r - g;
+        let yb = 0.5 * (r + g) - b;
+        sum_rg += rg;
+        sum_yb += yb;
+        sum_rg2 += rg * rg;
+        sum_yb2 += yb * yb;
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        if max == r && max > 64.0 && (max - min) / max > 0.45 && g.max(b) < r * 0.6 {
+            red_pixels += 1;
+        }
+    }
+    let count = total.max(1) as f64;
+    let (mean_rg, mean_yb) = (sum_rg / count, sum_yb / count);
+    let std_rg = (sum_rg2 / count - mean_rg * mean_rg).max(0.0).sqrt();
+    let std_yb = (sum_yb2 / count - mean_yb * mean_yb).max(0.0).sqrt();
+    let colorfulness = (std_rg * std_rg + std_yb * std_yb).sqrt()
+        + 0.3 * (mean_rg * mean_rg + mean_yb * mean_yb).sqrt();
+
+    Analysis {
+        low: percentile(0.02),
+        high: percentile(0.98),
+        noise: estimate_noise(&luma),
+        text_likeness: text_likeness(&luma, &histogram, total),
+        colorfulness: colorfulness as f32,
+        red_share: (red_pixels as f64 / count) as f32,
+        ink_threshold: ink_threshold(&luma, tone_lut),
+    }
+}
+
+/// Otsu returns the last dark level; the threshold stage turns levels at or
+/// above its cutoff white, so the cutoff starts one past it.
+fn ink_threshold(img: &GrayImage, tone_lut: &[u8]) -> u8 {
+    let mut toned = img.clone();
+    for pixel in toned.pixels_mut() {
+        pixel[0] = tone_lut[pixel[0] as usize];
+    }
+    crate::perspective::otsu_threshold(&toned).saturating_add(1)
+}
+
+/// Immerkaer's fast noise estimate: the mean absolute response of a
+/// Laplacian-difference kernel that cancels smooth image structure.
+fn estimate_noise(img: &GrayImage) -> f32 {
+    let (width, height) = img.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let at = |x: u32, y: u32| img.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let response = at(x - 1, y - 1) - 2.0 * at(x, y - 1) + at(x + 1, y - 1)
+                - 2.0 * at(x - 1, y)
+                + 4.0 * at(x, y)
+                - 2.0 * at(x + 1, y)
+                + at(x - 1, y + 1)
+                - 2.0 * at(x, y + 1)
+                + at(x + 1, y + 1);
+            sum += response.abs();
+        }
+    }
+
+    let samples = (6 * (width - 2) * (height - 2)) as f64;
+    (sum * (std::f64::consts::PI / 2.0).sqrt() / samples) as f32
+}
+
+/// Text and line art sit almost entirely at the two ends of the histogram
+/// and have sharp but sparse edges; photographs fill the midtones.
+fn text_likeness(img: &GrayImage, histogram: &[u64; 256], total: u64) -> f32 {
+    let extremes: u64 = histogram[..64].iter().chain(histogram[192..].iter()).sum();
+    let extreme_share = extremes as f32 / total.max(1) as f32;
+
+    let (width, height) = img.dimensions();
+    let mut strong_edges = 0u64;
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let center = img.get_pixel(x, y)[0] as i32;
+            let dx = (img.get_pixel(x + 1, y)[0] as i32 - center).abs();
+            let dy = (img.get_pixel(x, y + 1)[0] as i32 - center).abs();
+            if dx + dy > 96 {
+                strong_edges += 1;
+            }
+        }
+    }
+    let edge_share = strong_edges as f32 / total.max(1) as f32;
+
+    let bimodal = ((extreme_share - 0.6) / 0.35).clamp(0.0, 1.0);
+    let edge_factor = if (0.005..0.3).contains(&edge_share) {
+        1.0
+    } else {
+        0.5
+    };
+    bimodal * edge_factor
+}
+
+/// Scores every candidate for the image, best first.
+pub fn candidates(analysis: &Analysis) -> Vec<Candidate> {
+    let range = analysis.range();
+    let noise = analysis.noise;
+    let text = analysis.text_likeness;
+    let photo = 1.0 - text;
+    let wide_range = range > 0.7;
+    let narrow_range = range < 0.6;
+    let clean_source = noise < 2.0;
+    let colorful_poster = analysis.colorfulness > 60.0 && noise < 3.0;
+    // Distinct hues of similar brightness merge in grayscale; a little
+    // extra contrast keeps them apart.
+    let colorful = analysis.colorfulness > 50.0;
+    let color_boost = if colorful { 0.1 } else { 0.0 };
+    let range_text = format!("{}-{}", analysis.low, analysis.high);
+
+    // Each reason names only the measurements that raised the score.
+    let explain = |findings: Vec<String>, effect: &str| {
+        if findings.is_empty() {
+            effect.to_string()
+        } else {
+            format!("{}; {}", findings.join(", "), effect)
+        }
+    };
+    let color_finding = || {
+        colorful
+            .then(|| "colorful image, contrast raised to keep hues apart".to_string())
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+
+    let mut detail_findings = Vec::new();
+    if clean_source {
+        detail_findings.push(format!("clean source ({:.1} noise)", noise));
+    }
+    if wide_range {
+        detail_findings.push(format!("wide range ({})", range_text));
+    }
+    detail_findings.extend(color_finding());
+
+    let clean_findings = if clean_source {
+        color_finding()
+    } else {
+        let mut findings = vec![format!("noisy source ({:.1} noise)", noise)];
+        findings.extend(color_finding());
+        findings
+    };
+
+    let mut list = vec![
+        Candidate {
+            name: "photo",
+            score: 0.6 * photo,
+            reason: explain(color_finding(), "balanced defaults for typical photos"),
+            contrast: 1.3 + color_boost,
+            diffusion: 0.8,
+            mode: Mode::Dither,
+        },
+        Candidate {
+            name: "detail",
+            score: photo
+                * (0.35
+                    + if wide_range { 0.2 } else { 0.0 }
+                    + if clean_source { 0.2 } else { 0.0 }
+                    - (noise / 10.0).min(0.3)),
+            reason: explain(detail_findings, "full diffusion keeps fine detail"),
+            contrast: 1.1 + color_boost,
+            diffusion: 1.0,
+            mode: Mode::Dither,
+        },
+        Candidate {
+            name: "clean",
+            score: photo * (0.3 + (noise / 8.0).min(0.5)),
+            reason: explain(
+                clean_findings,
+                "lower diffusion keeps grain from turning into pepper noise",
+            ),
+            contrast: 1.3 + color_boost,
+            diffusion: 0.6,
+            mode: Mode::Dither,
+        },
+        Candidate {
+            name: "flat-boost",
+            score: photo * (0.3 + ((0.6 - range).max(0.0) * 2.0)),
+            reason: explain(
+                narrow_range
+                    .then(|| format!("narrow dynamic range ({})", range_text))
+                    .into_iter()
+                    .collect(),
+                "strong contrast spreads tones across the panel",
+            ),
+            contrast: (0.9 / range.max(0.05)).clamp(1.5, 2.0),
+            diffusion: 0.8,
+            mode: Mode::Dither,
+        },
+        Candidate {
+            name: "text",
+            score: 1.2 * text,
+            reason: explain(
+                (text >= 0.5)
+                    .then(|| "mostly black and white with sharp edges".to_string())
+                    .into_iter()
+                    .collect(),
+                "thresholding keeps strokes crisp",
+            ),
+            contrast: 1.0,
+            diffusion: 0.8,
+            mode: Mode::Threshold(analysis.ink_threshold),
+        },
+        Candidate {
+            name: "poster",
+            score: 0.25 * photo + if colorful_poster { 0.15 } else { 0.0 },
+            reason: explain(
+                colorful_poster
+                    .then(|| "bold, clean colors".to_string())
+                    .into_iter()
+                    .collect(),
+                "a clustered-dot screen gives a printed-poster look",
+            ),
+            contrast: 1.4,
+            diffusion: 0.8,
+            mode: Mode::Halftone,
+        },
+    ];
+
+    // Previews must match the printed flags, which carry one decimal.
+    for candidate in &mut list {
+        candidate.contrast = (candidate.contrast * 10.0).round() / 10.0;
+    }
+    list.sort_by(|a, b| b.score.total_cmp(&a.score));
+    list
+}
+
+/// Short human description of the colorfulness value.
+pub fn describe_colorfulness(colorfulness: f32) -> &'static str {
+    match colorfulness {
+        c if c < 15.0 => "nearly grayscale",
+        c if c < 35.0 => "slightly colorful",
+        c if c < 60.0 => "moderately colorful",
+        _ => "highly colorful",
+    }
+}
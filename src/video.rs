@@ -0,0 +1,162 @@
+// ABOUTME: Video input support for building slideshow sets from video files
+// ABOUTME: Samples frames through ffmpeg (behind the `video` feature) at fixed intervals or counts
+
+use image::DynamicImage;
+use std::path::Path;
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "mkv", "webm", "avi", "mpg", "mpeg"];
+
+/// How frames are picked from a video.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+pub enum Sampling {
+    /// One frame every given number of seconds, starting at the beginning.
+    Every(f64),
+    /// A fixed number of frames spread evenly across the whole video.
+    Frames(u32),
+}
+
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+impl Sampling {
+    /// Time in seconds of the sample with the given index, or `None` once
+    /// all samples have been taken.
+    fn time_of(self, index: usize, duration: f64) -> Option<f64> {
+        match self {
+            Sampling::Every(seconds) => Some(index as f64 * seconds),
+            Sampling::Frames(count) if (index as u32) < count => {
+                Some(duration * (index as f64 + 0.5) / count as f64)
+            }
+            Sampling::Frames(_) => None,
+        }
+    }
+}
+
+/// Returns true when the input path looks like a video container.
+pub fn is_video_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Decodes the video and hands each sampled frame, full resolution and in
+/// RGB, to `on_frame` with its index. Returns the number of frames sampled.
+#[cfg(feature = "video")]
+pub fn extract_frames<F>(
+    path: &str,
+    sampling: Sampling,
+    mut on_frame: F,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    F: FnMut(usize, DynamicImage) -> Result<(), Box<dyn std::error::Error>>,
+{
+    use ffmpeg_next as ffmpeg;
+    use ffmpeg_next::format::Pixel;
+    use ffmpeg_next::media::Type;
+    use ffmpeg_next::software::scaling::{context::Context, flag::Flags};
+    use ffmpeg_next::util::frame::video::Video;
+
+    ffmpeg::init()?;
+    let mut input = ffmpeg::format::input(path)?;
+    let duration = input.duration() as f64 * f64::from(ffmpeg::rescale::TIME_BASE);
+    if matches!(sampling, Sampling::Frames(_)) && duration <= 0.0 {
+        return Err("video duration is unknown; use --every instead of --frames".into());
+    }
+
+    let stream = input
+        .streams()
+        .best(Type::Video)
+        .ok_or("no video stream found")?;
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+    let mut decoder = context.decoder().video()?;
+
+    let mut scaler = Context::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::RGB24,
+        decoder.width(),
+        decoder.height(),
+        Flags::BICUBIC,
+    )?;
+
+    let mut sampled = 0;
+    let mut sample_index = 0;
+    let mut next_time = sampling.time_of(0, duration);
+
+    // Returns true once every requested sample has been taken.
+    let mut receive_frames =
+        |decoder: &mut ffmpeg::decoder::Video| -> Result<bool, Box<dyn std::error::Error>> {
+            let mut decoded = Video::empty();
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                let Some(target) = next_time else {
+                    return Ok(true);
+                };
+                let Some(timestamp) = decoded.timestamp() else {
+                    continue;
+                };
+                let now = timestamp as f64 * time_base;
+                if now < target {
+                    continue;
+                }
+
+                let mut rgb_frame = Video::empty();
+                scaler.run(&decoded, &mut rgb_frame)?;
+                on_frame(sampled, frame_to_image(&rgb_frame)?)?;
+                sampled += 1;
+
+                // One decoded frame covers every sample point up to its timestamp.
+                while next_time.is_some_and(|t| t <= now) {
+                    sample_index += 1;
+                    next_time = sampling.time_of(sample_index, duration);
+                }
+            }
+            Ok(next_time.is_none())
+        };
+
+    for (stream, packet) in input.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            if receive_frames(&mut decoder)? {
+                break;
+            }
+        }
+    }
+    decoder.send_eof()?;
+    receive_frames(&mut decoder)?;
+
+    Ok(sampled)
+}
+
+#[cfg(feature = "video")]
+fn frame_to_image(
+    frame: &ffmpeg_next::util::frame::video::Video,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let (width, height) = (frame.width(), frame.height());
+    let stride = frame.stride(0);
+    let row_len = width as usize * 3;
+    let data = frame.data(0);
+
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    for row in 0..height as usize {
+        pixels.extend_from_slice(&data[row * stride..row * stride + row_len]);
+    }
+
+    image::RgbImage::from_raw(width, height, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| "decoded frame has an unexpected size".into())
+}
+
+#[cfg(not(feature = "video"))]
+pub fn extract_frames<F>(
+    _path: &str,
+    _sampling: Sampling,
+    _on_frame: F,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    F: FnMut(usize, DynamicImage) -> Result<(), Box<dyn std::error::Error>>,
+{
+    Err("video input requires building with `--features video` (needs ffmpeg libraries)".into())
+}